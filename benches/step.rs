@@ -0,0 +1,82 @@
+//! Measures the per-step overhead of running a program non-interactively.
+//!
+//! Run with `cargo bench --bench step`.
+//!
+//! Fastest of five runs over three invocations, before and after tracking
+//! reads, writes and executed lines with bit sets instead of hash sets:
+//!
+//! ```text
+//!              before            after
+//!   step       90.5-93.2 ns      74.7-75.9 ns
+//!   tracking   57.3-65.8 ns      48.3-49.1 ns
+//! ```
+
+use elfdb::device::Device;
+use std::time::{Duration, Instant};
+
+/// Number of iterations per run.
+const STEPS: usize = 3_000_000;
+/// Number of runs, the fastest of which is reported.
+const RUNS: usize = 5;
+
+const PROGRAM: &str = "\
+#ip 5
+addi 0 1 0
+mulr 0 0 1
+gtrr 1 0 2
+addr 2 3 3
+seti 0 0 5
+";
+
+/// Run the given benchmark and report the fastest run.
+fn bench(name: &str, mut f: impl FnMut() -> Duration) {
+    let best = (0..RUNS).map(|_| f()).min().expect("no runs");
+    let nanos = best.as_secs() as f64 * 1e9 + f64::from(best.subsec_nanos());
+
+    println!(
+        "{}: {} iterations in {:?} ({:.1} ns/iter)",
+        name,
+        STEPS,
+        best,
+        nanos / STEPS as f64
+    );
+}
+
+fn main() {
+    // Executing a program, the way non-interactive mode does.
+    bench("step", || {
+        let mut device = Device::default();
+        device
+            .load(PROGRAM.lines())
+            .expect("failed to load program");
+
+        let start = Instant::now();
+
+        for _ in 0..STEPS {
+            device.clear();
+            device.step().expect("failed to step");
+        }
+
+        let elapsed = start.elapsed();
+        assert!(!device.halted, "device halted early");
+        elapsed
+    });
+
+    // Only the bookkeeping done for every step: clearing and filling the
+    // read and written registers, and recording the executed line.
+    bench("tracking", || {
+        let mut device = Device::default();
+
+        let start = Instant::now();
+
+        for i in 0..STEPS {
+            device.clear();
+            device.registers.reg(i % 6).expect("no register");
+            device.registers.reg((i + 1) % 6).expect("no register");
+            device.registers.reg_mut((i + 2) % 6).expect("no register");
+            device.unique.insert(i % 1000);
+        }
+
+        start.elapsed()
+    });
+}
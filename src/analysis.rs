@@ -0,0 +1,335 @@
+//! Infer the mapping of numeric op codes from samples.
+//!
+//! Samples are written the way puzzle inputs list them:
+//!
+//! ```text
+//! Before: [3, 2, 1, 1]
+//! 9 2 1 2
+//! After:  [3, 2, 2, 1]
+//! ```
+//!
+//! Every sample is tested against all op codes in an instruction set, and the
+//! op codes which are consistent with all samples for an id are narrowed down
+//! until every id maps to exactly one op code.
+
+use crate::{
+    arithmetic::Arithmetic,
+    op_code::{InstructionSet, OpCode, OpCodeMap, Operand},
+    registers::Registers,
+    Reg,
+};
+use failure::{bail, format_err, Error};
+use std::{collections::BTreeMap, path};
+
+/// A single sample of an instruction and the registers before and after it
+/// was executed.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// The line the sample starts on.
+    pub line: usize,
+    /// Registers before the instruction was executed.
+    pub before: Vec<Reg>,
+    /// The numeric op code of the instruction.
+    pub id: i64,
+    /// Inputs of the instruction.
+    pub inputs: [i64; 2],
+    /// Output of the instruction.
+    pub output: i64,
+    /// Registers after the instruction was executed.
+    pub after: Vec<Reg>,
+}
+
+impl Sample {
+    /// Test if the given op code behaves the way the sample describes, using
+    /// the given arithmetic semantics.
+    ///
+    /// Op codes which don't write to their output register, like `jmp`, never
+    /// match since a sample can't tell them apart from doing nothing.
+    pub fn matches(&self, op_code: OpCode, arithmetic: Arithmetic) -> bool {
+        if op_code.output() != Operand::Register {
+            return false;
+        }
+
+        let mut registers = Registers::default();
+
+        if registers.resize(self.before.len()).is_err()
+            || registers.set_arithmetic(arithmetic).is_err()
+        {
+            return false;
+        }
+
+        for (reg, value) in self.before.iter().enumerate() {
+            if registers.set(reg, value.clone()).is_err() {
+                return false;
+            }
+        }
+
+        if op_code
+            .apply(&mut registers, &self.inputs, self.output)
+            .is_err()
+        {
+            return false;
+        }
+
+        registers.iter().eq(self.after.iter().cloned())
+    }
+
+    /// Get all op codes in the given set which match this sample.
+    pub fn candidates(&self, set: &InstructionSet, arithmetic: Arithmetic) -> Vec<OpCode> {
+        set.iter()
+            .filter(|op_code| self.matches(*op_code, arithmetic))
+            .collect()
+    }
+}
+
+/// Load samples from the specified path, see [`parse_samples`].
+pub fn load_samples_path(path: impl AsRef<path::Path>) -> Result<Vec<Sample>, Error> {
+    use std::{fs::File, io::Read};
+
+    let mut f = File::open(path.as_ref())?;
+    let mut input = String::new();
+    f.read_to_string(&mut input)?;
+    parse_samples(input.lines())
+}
+
+/// Parse samples.
+///
+/// Lines which are not part of a sample, like the program which follows the
+/// samples in puzzle inputs, are ignored.
+pub fn parse_samples<'a>(input: impl Iterator<Item = &'a str>) -> Result<Vec<Sample>, Error> {
+    let mut samples = Vec::new();
+    let mut it = input.enumerate().map(|(n, line)| (n + 1, line.trim()));
+
+    while let Some((n, line)) = it.next() {
+        if !line.starts_with("Before:") {
+            continue;
+        }
+
+        let sample = (|| {
+            let before = registers(&line["Before:".len()..])?;
+
+            let (id, inputs, output) = match it.next() {
+                Some((_, line)) => instruction(line)?,
+                None => bail!("expected instruction"),
+            };
+
+            let after = match it.next() {
+                Some((_, line)) if line.starts_with("After:") => {
+                    registers(&line["After:".len()..])?
+                }
+                _ => bail!("expected `After: [..]`"),
+            };
+
+            if before.len() != after.len() {
+                bail!("number of registers before and after differ");
+            }
+
+            Ok(Sample {
+                line: n,
+                before,
+                id,
+                inputs,
+                output,
+                after,
+            })
+        })();
+
+        samples.push(sample.map_err(|e: Error| format_err!("sample on line {}: {}", n, e))?);
+    }
+
+    return Ok(samples);
+
+    /// Parse a list of registers, like `[3, 2, 1, 1]`.
+    fn registers(input: &str) -> Result<Vec<Reg>, Error> {
+        let input = input.trim();
+
+        if !input.starts_with('[') || !input.ends_with(']') {
+            bail!("expected registers, like `[3, 2, 1, 1]`");
+        }
+
+        input[1..input.len() - 1]
+            .split(',')
+            .map(|value| {
+                let value = value.trim();
+                str::parse::<Reg>(value).map_err(|e| format_err!("bad value `{}`: {}", value, e))
+            })
+            .collect()
+    }
+
+    /// Parse a numeric instruction, like `9 2 1 2`.
+    fn instruction(input: &str) -> Result<(i64, [i64; 2], i64), Error> {
+        let values = input
+            .split_whitespace()
+            .map(|value| {
+                str::parse::<i64>(value).map_err(|e| format_err!("bad value `{}`: {}", value, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match values.as_slice() {
+            &[id, a, b, c] => Ok((id, [a, b], c)),
+            _ => bail!("expected instruction, like `9 2 1 2`"),
+        }
+    }
+}
+
+/// The result of analyzing samples.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// Number of samples analyzed.
+    pub samples: usize,
+    /// Lines of the samples which match three or more op codes.
+    pub ambiguous: Vec<usize>,
+    /// Op codes which are consistent with every sample, by id.
+    pub candidates: BTreeMap<i64, Vec<OpCode>>,
+}
+
+impl Analysis {
+    /// Solve the constraints, producing a mapping where every id maps to a
+    /// unique op code.
+    ///
+    /// Fails if there's no such mapping, or if there's more than one.
+    pub fn solve(&self) -> Result<OpCodeMap, Error> {
+        // NB: the most constrained ids are tried first to prune the search.
+        let mut ids = self.candidates.keys().cloned().collect::<Vec<_>>();
+        ids.sort_by_key(|id| self.candidates[id].len());
+
+        let mut current = Vec::new();
+        let mut solutions = Vec::new();
+        search(&self.candidates, &ids, &mut current, &mut solutions);
+
+        let solution = match solutions.as_slice() {
+            [solution] => solution,
+            [] => bail!("no mapping is consistent with the samples"),
+            _ => bail!("the samples are consistent with more than one mapping"),
+        };
+
+        let mut map = OpCodeMap::default();
+
+        for (id, op_code) in ids.iter().zip(solution) {
+            map.insert(*id, *op_code);
+        }
+
+        return Ok(map);
+
+        /// Search for solutions, stopping as soon as a second one has been
+        /// found.
+        fn search(
+            candidates: &BTreeMap<i64, Vec<OpCode>>,
+            ids: &[i64],
+            current: &mut Vec<OpCode>,
+            solutions: &mut Vec<Vec<OpCode>>,
+        ) {
+            if solutions.len() > 1 {
+                return;
+            }
+
+            let id = match ids.get(current.len()) {
+                Some(id) => id,
+                None => {
+                    solutions.push(current.clone());
+                    return;
+                }
+            };
+
+            for op_code in &candidates[id] {
+                if current.contains(op_code) {
+                    continue;
+                }
+
+                current.push(*op_code);
+                search(candidates, ids, current, solutions);
+                current.pop();
+            }
+        }
+    }
+}
+
+/// Analyze the given samples, testing them against every op code in the
+/// instruction set using the given arithmetic semantics.
+pub fn analyze(set: &InstructionSet, arithmetic: Arithmetic, samples: &[Sample]) -> Analysis {
+    let mut ambiguous = Vec::new();
+    let mut candidates = BTreeMap::<i64, Vec<OpCode>>::new();
+
+    for sample in samples {
+        let matches = sample.candidates(set, arithmetic);
+
+        if matches.len() >= 3 {
+            ambiguous.push(sample.line);
+        }
+
+        candidates
+            .entry(sample.id)
+            .and_modify(|c| c.retain(|op_code| matches.contains(op_code)))
+            .or_insert(matches);
+    }
+
+    Analysis {
+        samples: samples.len(),
+        ambiguous,
+        candidates,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, parse_samples};
+    use crate::{
+        arithmetic::Arithmetic,
+        op_code::{InstructionSet, ADDI, ADDR, JMP, MULR, SETI, SETR},
+    };
+
+    #[test]
+    pub fn test_analyze() {
+        let samples = "Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]\n";
+        let samples = parse_samples(samples.lines()).expect("not parse");
+
+        let candidates = samples[0].candidates(&InstructionSet::default(), Arithmetic::default());
+        assert_eq!(candidates, vec![ADDI, MULR, SETI]);
+
+        let analysis = analyze(&InstructionSet::default(), Arithmetic::default(), &samples);
+        assert_eq!(analysis.ambiguous, vec![1]);
+
+        let samples = "\
+            Before: [2, 3, 0, 0]\n0 0 1 2\nAfter:  [2, 3, 5, 0]\n\n\
+            Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]\n";
+
+        let samples = parse_samples(samples.lines()).expect("not parse");
+        let analysis = analyze(&InstructionSet::default(), Arithmetic::default(), &samples);
+        assert_eq!(analysis.ambiguous, vec![5]);
+
+        let samples = "\
+            Before: [2, 3, 0, 0]\n0 0 1 2\nAfter:  [2, 3, 5, 0]\n\n\
+            Before: [2, 2, 0, 0]\n1 0 1 2\nAfter:  [2, 2, 4, 0]\n\n\
+            Before: [0, 0, 0, 0]\n2 7 0 2\nAfter:  [0, 0, 7, 0]\n\n\
+            \n\
+            0 1 2 3\n";
+
+        let samples = parse_samples(samples.lines()).expect("not parse");
+        assert_eq!(samples.len(), 3);
+
+        let mut set = InstructionSet::empty();
+        set.register(ADDR);
+        set.register(MULR);
+        set.register(SETI);
+
+        let map = analyze(&set, Arithmetic::default(), &samples)
+            .solve()
+            .expect("not solve");
+        assert_eq!(map.display().to_string(), "0 addr\n1 mulr\n2 seti\n");
+
+        // jmp doesn't match samples which leave the registers unchanged.
+        let samples = "Before: [1, 2, 3, 4]\n5 3 0 3\nAfter:  [1, 2, 3, 4]\n";
+        let samples = parse_samples(samples.lines()).expect("not parse");
+        let set = InstructionSet::extended();
+        assert!(!samples[0].matches(JMP, Arithmetic::default()));
+        assert!(samples[0]
+            .candidates(&set, Arithmetic::default())
+            .contains(&SETR));
+
+        // samples are checked with the given arithmetic.
+        let samples = "Before: [4294967295, 1, 0, 0]\n5 0 1 2\nAfter:  [4294967295, 1, 0, 0]\n";
+        let samples = parse_samples(samples.lines()).expect("not parse");
+        assert!(samples[0].matches(ADDR, Arithmetic::U32));
+        assert!(!samples[0].matches(ADDR, Arithmetic::default()));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::Reg;
+use failure::{bail, Error};
+use std::fmt;
+
+/// Arithmetic semantics used when evaluating operations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// 64-bit signed arithmetic, where overflow causes a fault.
+    #[default]
+    Checked,
+    /// 64-bit signed arithmetic, which wraps around on overflow.
+    Wrapping,
+    /// 32-bit unsigned arithmetic, all values are masked to 32 bits.
+    U32,
+    /// Arbitrary precision arithmetic.
+    Big,
+}
+
+impl fmt::Display for Arithmetic {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            Arithmetic::Checked => "checked",
+            Arithmetic::Wrapping => "wrapping",
+            Arithmetic::U32 => "u32",
+            Arithmetic::Big => "big",
+        };
+
+        name.fmt(fmt)
+    }
+}
+
+/// An arithmetic operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match *self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Rem => "%",
+            Op::And => "&",
+            Op::Or => "|",
+        };
+
+        op.fmt(fmt)
+    }
+}
+
+impl Op {
+    fn checked(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Op::Add => a.checked_add(b),
+            Op::Sub => a.checked_sub(b),
+            Op::Mul => a.checked_mul(b),
+            Op::Div => a.checked_div(b),
+            Op::Rem => a.checked_rem(b),
+            Op::And => Some(a & b),
+            Op::Or => Some(a | b),
+        }
+    }
+
+    fn wrapping(self, a: i64, b: i64) -> i64 {
+        match self {
+            Op::Add => a.wrapping_add(b),
+            Op::Sub => a.wrapping_sub(b),
+            Op::Mul => a.wrapping_mul(b),
+            Op::Div => a.wrapping_div(b),
+            Op::Rem => a.wrapping_rem(b),
+            Op::And => a & b,
+            Op::Or => a | b,
+        }
+    }
+}
+
+/// Mask used for 32-bit arithmetic.
+const U32_MASK: i64 = 0xffff_ffff;
+
+impl Arithmetic {
+    /// Look up an arithmetic mode by name.
+    pub fn by_name(name: &str) -> Option<Arithmetic> {
+        let out = match name {
+            "checked" => Arithmetic::Checked,
+            "wrapping" => Arithmetic::Wrapping,
+            "u32" => Arithmetic::U32,
+            "big" => Arithmetic::Big,
+            _ => return None,
+        };
+
+        Some(out)
+    }
+
+    /// Names of all available modes.
+    pub fn names() -> &'static [&'static str] {
+        &["checked", "wrapping", "u32", "big"]
+    }
+
+    /// Normalize a value so that it can be stored in a register using these
+    /// semantics.
+    pub fn normalize(self, value: Reg) -> Result<Reg, Error> {
+        let value = match self {
+            Arithmetic::Checked => match value {
+                Reg::Small(..) => value,
+                Reg::Big(..) => bail!("value {} doesn't fit in 64 bits", value),
+            },
+            Arithmetic::Wrapping => Reg::Small(value.truncate()),
+            Arithmetic::U32 => Reg::Small(value.truncate() & U32_MASK),
+            Arithmetic::Big => value,
+        };
+
+        Ok(value)
+    }
+
+    /// Evaluate the given operation.
+    pub fn eval(self, op: Op, a: &Reg, b: &Reg) -> Result<Reg, Error> {
+        if let Op::Div | Op::Rem = op {
+            if b.is_zero() {
+                bail!("division by zero: {} {} {}", a, op, b);
+            }
+        }
+
+        let value = match self {
+            Arithmetic::Checked => {
+                let (a, b) = match (a.as_i64(), b.as_i64()) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => bail!("value doesn't fit in 64 bits"),
+                };
+
+                match op.checked(a, b) {
+                    Some(value) => Reg::Small(value),
+                    None => bail!("arithmetic overflow: {} {} {}", a, op, b),
+                }
+            }
+            Arithmetic::Wrapping => Reg::Small(op.wrapping(a.truncate(), b.truncate())),
+            Arithmetic::U32 => {
+                let value = op.wrapping(a.truncate() & U32_MASK, b.truncate() & U32_MASK);
+                Reg::Small(value & U32_MASK)
+            }
+            Arithmetic::Big => {
+                if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+                    if let Some(value) = op.checked(a, b) {
+                        return Ok(Reg::Small(value));
+                    }
+                }
+
+                let (a, b) = (a.to_big(), b.to_big());
+
+                Reg::big(match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Rem => a % b,
+                    Op::And => a & b,
+                    Op::Or => a | b,
+                })
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arithmetic, Op};
+    use crate::Reg;
+
+    #[test]
+    pub fn test_arithmetic() {
+        let max = Reg::from(i64::MAX);
+        let one = Reg::from(1);
+
+        assert!(Arithmetic::Checked.eval(Op::Add, &max, &one).is_err());
+        assert_eq!(
+            Arithmetic::Wrapping.eval(Op::Add, &max, &one).unwrap(),
+            Reg::from(i64::MIN)
+        );
+        assert_eq!(
+            Arithmetic::U32.eval(Op::Sub, &Reg::from(0), &one).unwrap(),
+            Reg::from(0xffff_ffff)
+        );
+
+        let big = Arithmetic::Big.eval(Op::Add, &max, &one).unwrap();
+        assert_eq!(big.to_string(), "9223372036854775808");
+        assert_eq!(Arithmetic::Big.eval(Op::Sub, &big, &one).unwrap(), max);
+    }
+}
@@ -0,0 +1,575 @@
+//! An assembler for ElfCode.
+//!
+//! The assembler accepts the `#ip`, `#regs`, `#reg` and `#name` directives of
+//! the regular loader, but not numeric op codes or the `#opcode`, `#opcodes`
+//! and `#isa` directives. It also accepts:
+//!
+//! * Labels, like `loop:`, which can be used anywhere an immediate is expected.
+//!   Labels can't have the same name as a register.
+//! * Symbolic registers, like `a`, `ip`, or aliases declared with `#name`.
+//! * Commas between operands, like `addi a, 1, a`.
+//! * Pseudo-instructions: `jmp <label>`, `halt`, and conditional jumps like
+//!   `jeq <a>, <b>, <label>` (also `jne`, `jgt`, `jge`, `jlt` and `jle`).
+//!
+//! Conditional jumps need a register to store the result of the comparison in,
+//! which is declared with `#scratch <reg>`. Like the loader, `#regs <count>`
+//! changes the number of registers available.
+
+use crate::{
+    device::split_comment,
+    instruction::Instruction,
+    op_code::{self, InstructionSet, OpCode, Operand},
+    registers::{self, is_identifier, plain_name, Registers},
+    Reg,
+};
+use failure::{bail, format_err, Error};
+use hashbrown::HashMap;
+use std::{fmt, path};
+
+/// An assembled program.
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// The number of registers.
+    pub registers: usize,
+    /// The register bound to the instruction pointer, if any.
+    pub ip: Option<usize>,
+    /// Register aliases declared with `#name`.
+    pub aliases: Vec<(usize, String)>,
+    /// Initial values of registers declared with `#reg`.
+    pub initial: Vec<(usize, Reg)>,
+    /// Assembled instructions.
+    pub instructions: Vec<Instruction>,
+}
+
+impl Program {
+    /// Display the program as plain ElfCode.
+    pub fn display(&self) -> Display<'_> {
+        Display { program: self }
+    }
+}
+
+pub struct Display<'a> {
+    program: &'a Program,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.program.registers != registers::DEFAULT_LEN {
+            writeln!(fmt, "#regs {}", self.program.registers)?;
+        }
+
+        if let Some(ip) = self.program.ip {
+            writeln!(fmt, "#ip {}", ip)?;
+        }
+
+        if !self.program.initial.is_empty() {
+            write!(fmt, "#reg")?;
+
+            for (reg, value) in &self.program.initial {
+                write!(fmt, " {}={}", plain_name(*reg), value)?;
+            }
+
+            writeln!(fmt)?;
+        }
+
+        for inst in &self.program.instructions {
+            writeln!(fmt, "{}", inst.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Conditions supported by conditional jumps.
+#[derive(Debug, Clone, Copy)]
+enum Cond {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Cond {
+    fn decode(input: &str) -> Option<Cond> {
+        let out = match input {
+            "jeq" => Cond::Eq,
+            "jne" => Cond::Ne,
+            "jgt" => Cond::Gt,
+            "jge" => Cond::Ge,
+            "jlt" => Cond::Lt,
+            "jle" => Cond::Le,
+            _ => return None,
+        };
+
+        Some(out)
+    }
+
+    /// Decompose the condition into a comparison, if the operands should be
+    /// swapped, and if the comparison should be negated.
+    fn decompose(self) -> (Compare, bool, bool) {
+        match self {
+            Cond::Eq => (Compare::Eq, false, false),
+            Cond::Ne => (Compare::Eq, false, true),
+            Cond::Gt => (Compare::Gt, false, false),
+            Cond::Le => (Compare::Gt, false, true),
+            Cond::Lt => (Compare::Gt, true, false),
+            Cond::Ge => (Compare::Gt, true, true),
+        }
+    }
+
+    /// Number of instructions the jump expands into.
+    fn len(self) -> usize {
+        match self.decompose() {
+            (_, _, true) => 3,
+            (_, _, false) => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Compare {
+    Eq,
+    Gt,
+}
+
+/// A single statement in the source.
+#[derive(Debug)]
+enum Statement<'a> {
+    /// A regular instruction.
+    Inst(OpCode, Vec<&'a str>),
+    /// An unconditional jump.
+    Jmp(&'a str),
+    /// A conditional jump.
+    Jump(Cond, [&'a str; 3]),
+    /// Halt the program.
+    Halt,
+}
+
+impl Statement<'_> {
+    /// Number of instructions this statement expands into.
+    fn len(&self) -> usize {
+        match *self {
+            Statement::Jump(cond, _) => cond.len(),
+            _ => 1,
+        }
+    }
+}
+
+/// An operand which might either be a register or an immediate.
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Register(i64),
+    Immediate(i64),
+}
+
+struct Assembler<'a> {
+    instruction_set: &'a InstructionSet,
+    registers: Registers,
+    scratch: Option<usize>,
+    labels: HashMap<&'a str, usize>,
+    statements: Vec<(usize, Statement<'a>, Option<String>)>,
+    /// Comments on lines of their own, waiting for a statement.
+    comments: Vec<&'a str>,
+    /// Total number of instructions.
+    len: usize,
+}
+
+impl<'a> Assembler<'a> {
+    fn line(&mut self, n: usize, line: &'a str) -> Result<(), Error> {
+        let (code, comment) = split_comment(line);
+        let mut code = code.trim();
+
+        if code.starts_with('#') {
+            return self.directive(code);
+        }
+
+        if let Some(index) = code.find(':') {
+            let label = code[..index].trim();
+
+            if !is_identifier(label) {
+                bail!("bad label `{}`", label);
+            }
+
+            if self.registers.lookup(label).is_some() {
+                bail!("label `{}` has the same name as a register", label);
+            }
+
+            if self.labels.insert(label, self.len).is_some() {
+                bail!("label `{}` is already defined", label);
+            }
+
+            code = code[index + 1..].trim();
+        }
+
+        if code.is_empty() {
+            self.comments.extend(comment);
+            return Ok(());
+        }
+
+        let mut it = code
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty());
+
+        let name = it
+            .next()
+            .ok_or_else(|| format_err!("expected instruction"))?;
+        let args = it.collect::<Vec<_>>();
+
+        let statement = match name {
+            "jmp" => match args.as_slice() {
+                &[label] => Statement::Jmp(label),
+                _ => bail!("expected: jmp <label>"),
+            },
+            "halt" => match args.as_slice() {
+                &[] => Statement::Halt,
+                _ => bail!("expected: halt"),
+            },
+            name => match (Cond::decode(name), self.instruction_set.decode(name)) {
+                (Some(cond), _) => match args.as_slice() {
+                    &[a, b, label] => Statement::Jump(cond, [a, b, label]),
+                    _ => bail!("expected: {} <a>, <b>, <label>", name),
+                },
+                (None, Some(op_code)) => Statement::Inst(op_code, args),
+                (None, None) => bail!("no such instruction: {}", name),
+            },
+        };
+
+        self.comments.extend(comment);
+
+        let comment = if self.comments.is_empty() {
+            match statement {
+                Statement::Inst(..) => None,
+                _ => Some(code.to_string()),
+            }
+        } else {
+            let comment = self.comments.join("; ");
+            self.comments.clear();
+            Some(comment)
+        };
+
+        self.len += statement.len();
+        self.statements.push((n, statement, comment));
+        Ok(())
+    }
+
+    fn directive(&mut self, line: &str) -> Result<(), Error> {
+        let mut it = line.split_whitespace();
+
+        match it.next() {
+            Some("#regs") => {
+                let len = it
+                    .next()
+                    .ok_or_else(|| format_err!("expected argument to `#regs`"))
+                    .and_then(|arg| {
+                        str::parse(arg).map_err(|e| format_err!("bad argument to `#regs`: {}", e))
+                    })?;
+
+                self.registers.resize(len)?;
+            }
+            Some("#reg") => {
+                self.registers.parse_initial(it)?;
+            }
+            Some("#ip") => {
+                let reg = it
+                    .next()
+                    .ok_or_else(|| format_err!("expected argument to `#ip`"))?;
+                self.registers.ip = Some(self.register(reg)? as usize);
+            }
+            Some("#name") => {
+                let (name, alias) = match (it.next(), it.next()) {
+                    (Some(name), Some(alias)) => (name, alias),
+                    _ => bail!("expected: #name <reg> <alias>"),
+                };
+
+                if self.labels.contains_key(alias) {
+                    bail!("alias `{}` has the same name as a label", alias);
+                }
+
+                let reg = self.register(name)?;
+                self.registers.set_alias(reg, alias)?;
+            }
+            Some("#scratch") => {
+                let reg = it
+                    .next()
+                    .ok_or_else(|| format_err!("expected argument to `#scratch`"))?;
+                self.scratch = Some(self.register(reg)? as usize);
+            }
+            Some(other) => bail!("unsupported directive: {}", other),
+            None => bail!("expected directive"),
+        }
+
+        Ok(())
+    }
+
+    /// Resolve a register, either by name or by index.
+    fn register(&self, input: &str) -> Result<i64, Error> {
+        if let Some(reg) = self.registers.lookup(input) {
+            return Ok(reg as i64);
+        }
+
+        match str::parse::<usize>(input) {
+            Ok(reg) if reg < self.registers.len() => Ok(reg as i64),
+            _ => bail!("not a register: {}", input),
+        }
+    }
+
+    /// Resolve an immediate, either a number or a label.
+    fn immediate(&self, input: &str) -> Result<i64, Error> {
+        if let Some(address) = self.labels.get(input) {
+            return Ok(*address as i64);
+        }
+
+        match str::parse::<i64>(input) {
+            Ok(value) => Ok(value),
+            Err(_) => bail!("not an immediate or label: {}", input),
+        }
+    }
+
+    /// Resolve a value which might either be a register or an immediate.
+    fn value(&self, input: &str) -> Result<Value, Error> {
+        if let Some(reg) = self.registers.lookup(input) {
+            return Ok(Value::Register(reg as i64));
+        }
+
+        Ok(Value::Immediate(self.immediate(input)?))
+    }
+
+    /// Resolve the target of a jump.
+    ///
+    /// Since the instruction pointer is incremented after each instruction, we
+    /// need to jump to one before the actual target.
+    fn target(&self, input: &str) -> Result<i64, Error> {
+        match self.labels.get(input) {
+            Some(address) => Ok(*address as i64 - 1),
+            None => bail!("no such label: {}", input),
+        }
+    }
+
+    /// The register bound to the instruction pointer, which is required by
+    /// jumps.
+    fn ip(&self) -> Result<i64, Error> {
+        match self.registers.ip {
+            Some(ip) => Ok(ip as i64),
+            None => bail!("jumps require the instruction pointer to be bound, see `#ip`"),
+        }
+    }
+
+    /// Emit the instructions for a single statement.
+    fn emit(&self, statement: &Statement<'_>, out: &mut Vec<Instruction>) -> Result<(), Error> {
+        use self::op_code::{ADDI, ADDR, EQIR, EQRI, EQRR, GTIR, GTRI, GTRR, SETI};
+
+        match *statement {
+            Statement::Inst(op_code, ref args) => {
+                let operands = op_code.operands();
+
+                let args = match (args.as_slice(), operands[1]) {
+                    (&[a, b, o], _) => [a, b, o],
+                    (&[a, o], Operand::Ignored) => [a, "0", o],
+                    _ => bail!("expected: {} <a>, <b>, <c>", op_code),
+                };
+
+                let mut inputs = [0; 2];
+
+                for ((input, operand), arg) in inputs.iter_mut().zip(&operands).zip(&args) {
+                    *input = match *operand {
+                        Operand::Register => self.register(arg)?,
+                        Operand::Immediate | Operand::Ignored => self.immediate(arg)?,
+                    };
+                }
+
+                out.push(inst(op_code, inputs, self.register(args[2])?));
+            }
+            Statement::Jmp(label) => {
+                let ip = self.ip()?;
+                out.push(inst(SETI, [self.target(label)?, 0], ip));
+            }
+            Statement::Halt => {
+                let ip = self.ip()?;
+                out.push(inst(SETI, [self.len as i64, 0], ip));
+            }
+            Statement::Jump(cond, [a, b, label]) => {
+                let ip = self.ip()?;
+
+                let scratch = match self.scratch {
+                    Some(scratch) => scratch as i64,
+                    None => bail!("conditional jumps require a scratch register, see `#scratch`"),
+                };
+
+                if scratch == ip {
+                    bail!("scratch register can't be the instruction pointer");
+                }
+
+                let (compare, swap, negate) = cond.decompose();
+
+                let (a, b) = if swap { (b, a) } else { (a, b) };
+
+                let (op_code, inputs) = match (compare, self.value(a)?, self.value(b)?) {
+                    (Compare::Eq, Value::Register(a), Value::Register(b)) => (EQRR, [a, b]),
+                    (Compare::Eq, Value::Register(a), Value::Immediate(b)) => (EQRI, [a, b]),
+                    (Compare::Eq, Value::Immediate(a), Value::Register(b)) => (EQIR, [a, b]),
+                    (Compare::Gt, Value::Register(a), Value::Register(b)) => (GTRR, [a, b]),
+                    (Compare::Gt, Value::Register(a), Value::Immediate(b)) => (GTRI, [a, b]),
+                    (Compare::Gt, Value::Immediate(a), Value::Register(b)) => (GTIR, [a, b]),
+                    (_, Value::Immediate(..), Value::Immediate(..)) => {
+                        bail!("conditional jumps require at least one register")
+                    }
+                };
+
+                out.push(inst(op_code, inputs, scratch));
+                // skip the next instruction if the comparison holds.
+                out.push(inst(ADDR, [scratch, ip], ip));
+
+                if !negate {
+                    // comparison doesn't hold, skip the jump.
+                    out.push(inst(ADDI, [ip, 1], ip));
+                }
+
+                out.push(inst(SETI, [self.target(label)?, 0], ip));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Assemble the given source into a program, using the given instruction set.
+pub fn assemble<'a>(
+    instruction_set: &'a InstructionSet,
+    input: impl Iterator<Item = &'a str>,
+) -> Result<Program, Error> {
+    let mut assembler = Assembler {
+        instruction_set,
+        registers: Registers::default(),
+        scratch: None,
+        labels: HashMap::new(),
+        statements: Vec::new(),
+        comments: Vec::new(),
+        len: 0,
+    };
+
+    for (n, line) in input.enumerate() {
+        assembler
+            .line(n + 1, line)
+            .map_err(|e| format_err!("line {}: {}", n + 1, e))?;
+    }
+
+    let mut instructions = Vec::new();
+
+    for (line, statement, comment) in &assembler.statements {
+        let start = instructions.len();
+
+        assembler
+            .emit(statement, &mut instructions)
+            .map_err(|e| format_err!("line {}: {}", line, e))?;
+
+        if let Some(inst) = instructions.get_mut(start) {
+            inst.comment = comment.clone();
+        }
+    }
+
+    let registers = &assembler.registers;
+
+    let aliases = (0..registers.len())
+        .flat_map(|reg| registers.alias(reg).map(|alias| (reg, alias.to_string())))
+        .collect();
+
+    let initial = (0..registers.len())
+        .flat_map(|reg| registers.initial(reg).map(|value| (reg, value.clone())))
+        .filter(|(_, value)| *value != Reg::default())
+        .collect();
+
+    Ok(Program {
+        registers: registers.len(),
+        ip: registers.ip,
+        aliases,
+        initial,
+        instructions,
+    })
+}
+
+/// Assemble the program at the given path, using the given instruction set.
+pub fn assemble_path(
+    instruction_set: &InstructionSet,
+    path: impl AsRef<path::Path>,
+) -> Result<Program, Error> {
+    use std::{fs::File, io::Read};
+
+    let mut f = File::open(path.as_ref())?;
+    let mut input = String::new();
+    f.read_to_string(&mut input)?;
+    assemble(instruction_set, input.lines())
+}
+
+/// Construct a new instruction.
+fn inst(op_code: OpCode, inputs: [i64; 2], output: i64) -> Instruction {
+    Instruction {
+        op_code,
+        inputs,
+        output,
+        comment: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::{device::Device, op_code::InstructionSet, Reg};
+
+    #[test]
+    pub fn test_assemble() {
+        let source = "
+            #ip f
+            #scratch e
+            #name c counter
+            seti 10 0 counter
+        loop:
+            addi counter, -1, counter
+            jne counter, 0, loop   ; count down
+            halt
+        ";
+
+        let set = InstructionSet::default();
+        let program = assemble(&set, source.lines()).expect("not assemble");
+
+        assert_eq!(
+            program.display().to_string(),
+            "#ip 5\nseti 10 0 2\naddi 2 -1 2\neqri 2 0 4\naddr 4 5 5\nseti 0 0 5\nseti 6 0 5\n"
+        );
+
+        let mut device = Device::default();
+        device.load_program(program).expect("not load");
+
+        while !device.halted {
+            device.step().expect("not step");
+        }
+
+        assert_eq!(device.registers.iter().nth(2), Some(Reg::from(0)));
+        assert_eq!(device.count, 41);
+    }
+
+    #[test]
+    pub fn test_initial_values_and_labels() {
+        let source = "#ip 5\n#reg a=3 d=-1\nloop: addi a, -1, a\n";
+
+        let set = InstructionSet::default();
+        let program = assemble(&set, source.lines()).expect("not assemble");
+
+        assert_eq!(
+            program.display().to_string(),
+            "#ip 5\n#reg a=3 d=-1\naddi 0 -1 0\n"
+        );
+
+        let mut device = Device::default();
+        device.load_program(program).expect("not load");
+        assert_eq!(device.registers.iter().next(), Some(Reg::from(3)));
+        assert_eq!(device.registers.iter().nth(3), Some(Reg::from(-1)));
+
+        let error = assemble(&set, "b: seti 0 0 b\n".lines()).expect_err("labels shadow b");
+        assert_eq!(
+            error.to_string(),
+            "line 1: label `b` has the same name as a register"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+/// Number of bits in a single word.
+const BITS: usize = 64;
+
+/// A dense set of small integers, like register indexes or line numbers.
+///
+/// Storage grows to fit the largest member, and clearing the set keeps its
+/// storage so that it can be cheaply refilled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    /// Construct a new, empty set.
+    pub fn new() -> BitSet {
+        BitSet::default()
+    }
+
+    /// Insert the given value, returns `true` if it wasn't already present.
+    pub fn insert(&mut self, value: usize) -> bool {
+        let (word, bit) = (value / BITS, 1 << (value % BITS));
+
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+
+        let word = &mut self.words[word];
+
+        if *word & bit != 0 {
+            return false;
+        }
+
+        *word |= bit;
+        self.len += 1;
+        true
+    }
+
+    /// Test if the set contains the given value.
+    pub fn contains(&self, value: usize) -> bool {
+        match self.words.get(value / BITS) {
+            Some(word) => word & (1 << (value % BITS)) != 0,
+            None => false,
+        }
+    }
+
+    /// Number of values in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Test if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Remove all values from the set.
+    pub fn clear(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+
+        for word in &mut self.words {
+            *word = 0;
+        }
+
+        self.len = 0;
+    }
+
+    /// Remove all values which are not less than `len`.
+    pub fn truncate(&mut self, len: usize) {
+        let (word, bits) = (len / BITS, len % BITS);
+
+        if word >= self.words.len() {
+            return;
+        }
+
+        self.words.truncate(word + 1);
+
+        if let Some(last) = self.words.last_mut() {
+            *last &= (1 << bits) - 1;
+        }
+
+        self.len = self.words.iter().map(|w| w.count_ones() as usize).sum();
+    }
+
+    /// Iterate over all values in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            (0..BITS)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * BITS + bit)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitSet;
+
+    #[test]
+    pub fn test_bit_set() {
+        let mut set = BitSet::new();
+
+        assert!(set.insert(3));
+        assert!(set.insert(64));
+        assert!(set.insert(200));
+        assert!(!set.insert(64));
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(200));
+        assert!(!set.contains(4));
+        assert!(!set.contains(1000));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 64, 200]);
+
+        set.truncate(64);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(set.len(), 1);
+
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.contains(3));
+    }
+}
@@ -0,0 +1,599 @@
+//! Breakpoints, which pair a hook with effects to run when the hook fires.
+//!
+//! Effects are written after the condition, separated by `do` and `;`:
+//!
+//! ```text
+//! line(28) do log "c = {c}"; count hits; continue
+//! ```
+
+use crate::{
+    device::Device,
+    expr::Expr,
+    hook::{Action, Hook},
+    parser, Reg,
+};
+use failure::{bail, format_err, Error};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{BufWriter, Write as _},
+};
+
+/// Named counters, incremented by the `count` effect.
+pub type Counters = BTreeMap<String, u64>;
+
+/// A breakpoint.
+pub struct Breakpoint {
+    /// The condition of the breakpoint.
+    pub hook: Hook,
+    /// Effects to run when the breakpoint fires.
+    pub effects: Vec<Effect>,
+    /// Number of times the hook has fired, including ignored hits.
+    pub hits: u64,
+    /// Number of upcoming hits to ignore.
+    pub ignore: u64,
+    /// If the breakpoint is enabled. Disabled breakpoints are not tested, but
+    /// keep the state of their hook.
+    pub enabled: bool,
+    /// If the breakpoint should be removed after it fired.
+    pub temporary: bool,
+}
+
+impl Breakpoint {
+    /// Construct a breakpoint which pauses when the hook fires.
+    pub fn new(hook: Hook) -> Breakpoint {
+        Breakpoint {
+            hook,
+            effects: Vec::new(),
+            hits: 0,
+            ignore: 0,
+            enabled: true,
+            temporary: false,
+        }
+    }
+
+    /// Parse a breakpoint, like `line(28) do log "c = {c}"; continue`.
+    pub fn parse(input: &str, device: &Device) -> Result<Breakpoint, Error> {
+        let (condition, effects) = match find_outside_quotes(input, " do ") {
+            Some(n) => (&input[..n], Some(&input[n + " do ".len()..])),
+            None => (input, None),
+        };
+
+        let hook = parser::parse(condition, device)?;
+        let mut breakpoint = Breakpoint::new(hook);
+
+        if let Some(mut effects) = effects {
+            while !effects.trim().is_empty() {
+                let (effect, rest) = match find_outside_quotes(effects, ";") {
+                    Some(n) => (&effects[..n], &effects[n + 1..]),
+                    None => (effects, ""),
+                };
+
+                breakpoint
+                    .effects
+                    .push(Effect::parse(effect.trim(), device)?);
+                effects = rest;
+            }
+        }
+
+        Ok(breakpoint)
+    }
+
+    /// Parse a tracepoint, which is a breakpoint that never pauses.
+    ///
+    /// Without any effects, the value of every register is logged.
+    pub fn trace(input: &str, device: &Device) -> Result<Breakpoint, Error> {
+        let mut breakpoint = Breakpoint::parse(input, device)?;
+
+        if breakpoint.effects.is_empty() {
+            // NB: the register bound to the instruction pointer is already
+            // logged as `ip`.
+            let format = (0..device.registers.len())
+                .filter(|reg| device.registers.ip != Some(*reg))
+                .map(|reg| {
+                    let name = device.registers.name(reg).to_string();
+                    format!("{}={{{}}}", name, name)
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let format = format!("ip={{ip}} {}", format);
+            breakpoint
+                .effects
+                .push(Effect::Log(Format::parse(&format, device)?));
+        }
+
+        if !breakpoint.is_trace() {
+            breakpoint.effects.push(Effect::Continue);
+        }
+
+        Ok(breakpoint)
+    }
+
+    /// Test if the breakpoint is a tracepoint, which continues running
+    /// instead of pausing.
+    pub fn is_trace(&self) -> bool {
+        self.effects.iter().any(|e| matches!(e, Effect::Continue))
+    }
+
+    /// Reset the state of the breakpoint.
+    pub fn reset(&mut self) {
+        self.hook.reset();
+        self.hits = 0;
+    }
+
+    /// Inspect the state of the breakpoint.
+    pub fn inspect(&self) -> crate::hook::Inspect<'_> {
+        self.hook.inspect()
+    }
+
+    /// Test if the breakpoint fires.
+    ///
+    /// Every time the hook fires counts as a hit, but the breakpoint only
+    /// fires once the ignore count has run out.
+    pub fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        if !self.enabled {
+            return Ok(Action::None);
+        }
+
+        match self.hook.test(device)? {
+            Action::Pause => {
+                self.hits += 1;
+
+                if self.ignore > 0 {
+                    self.ignore -= 1;
+                    return Ok(Action::None);
+                }
+
+                Ok(Action::Pause)
+            }
+            Action::None => Ok(Action::None),
+        }
+    }
+
+    /// Run the effects of the breakpoint, after it fired.
+    ///
+    /// Logged messages are added to `log`. Returns `true` if the device
+    /// should pause.
+    pub fn fire(
+        &mut self,
+        device: &mut Device,
+        counters: &mut Counters,
+        log: &mut Vec<String>,
+    ) -> Result<bool, Error> {
+        let mut pause = true;
+
+        for effect in &mut self.effects {
+            match *effect {
+                Effect::Log(ref format) => {
+                    log.push(format.eval(device)?);
+                }
+                Effect::LogFile(ref format, ref path, ref mut file) => {
+                    let line = format.eval(device)?;
+                    writeln!(file, "{}", line)
+                        .map_err(|e| format_err!("failed to write to `{}`: {}", path, e))?;
+                }
+                Effect::Set(reg, ref expr) => {
+                    let value = expr.eval(&device.registers)?;
+                    device.registers.set(reg, value)?;
+                }
+                Effect::Count(ref name) => {
+                    *counters.entry(name.clone()).or_default() += 1;
+                }
+                Effect::Continue => {
+                    pause = false;
+                }
+            }
+        }
+
+        Ok(pause)
+    }
+
+    /// Flush messages which have been logged to files.
+    ///
+    /// Called when the device pauses or halts, since files are written
+    /// through a buffer.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        for effect in &mut self.effects {
+            if let Effect::LogFile(_, ref path, ref mut file) = *effect {
+                file.flush()
+                    .map_err(|e| format_err!("failed to write to `{}`: {}", path, e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Display the breakpoint.
+    pub fn display<'a>(&'a self, device: &'a Device) -> Display<'a> {
+        Display {
+            breakpoint: self,
+            device,
+        }
+    }
+}
+
+pub struct Display<'a> {
+    breakpoint: &'a Breakpoint,
+    device: &'a Device,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Display { breakpoint, device } = *self;
+
+        breakpoint.hook.display(device).fmt(fmt)?;
+
+        let mut it = breakpoint.effects.iter().peekable();
+
+        if it.peek().is_some() {
+            write!(fmt, " do ")?;
+        }
+
+        while let Some(effect) = it.next() {
+            effect.display(device).fmt(fmt)?;
+
+            if it.peek().is_some() {
+                write!(fmt, "; ")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An effect of a breakpoint firing.
+pub enum Effect {
+    /// Log a message, like `log "c = {c}"`.
+    Log(Format),
+    /// Append a message to a file, like `log >> trace.log "{c}"`.
+    LogFile(Format, String, BufWriter<File>),
+    /// Set a register, like `set c c + 1`.
+    Set(usize, Expr),
+    /// Increment a named counter, like `count hits`.
+    Count(String),
+    /// Continue running instead of pausing.
+    Continue,
+}
+
+impl Effect {
+    /// Parse a single effect.
+    pub fn parse(input: &str, device: &Device) -> Result<Effect, Error> {
+        let (name, rest) = match input.find(' ') {
+            Some(n) => (&input[..n], input[n..].trim()),
+            None => (input, ""),
+        };
+
+        let effect = match name {
+            "log" if rest.starts_with(">>") => {
+                let rest = rest[">>".len()..].trim();
+
+                let (path, format) = match rest.find(' ') {
+                    Some(n) => (&rest[..n], rest[n..].trim()),
+                    None => bail!("expected: log >> <path> <format>"),
+                };
+
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format_err!("failed to open `{}`: {}", path, e))?;
+
+                Effect::LogFile(
+                    Format::parse(format, device)?,
+                    path.to_string(),
+                    BufWriter::new(file),
+                )
+            }
+            "log" => Effect::Log(Format::parse(rest, device)?),
+            "set" => {
+                let (reg, expr) = match rest.find(' ') {
+                    Some(n) => (&rest[..n], rest[n..].trim()),
+                    None => bail!("expected: set <reg> <expr>"),
+                };
+
+                let reg = match device.registers.lookup(reg) {
+                    Some(reg) => reg,
+                    None => bail!("no such register `{}`", reg),
+                };
+
+                Effect::Set(reg, parser::parse_expr(expr, device)?)
+            }
+            "count" => {
+                if !crate::registers::is_identifier(rest) {
+                    bail!("expected: count <name>");
+                }
+
+                Effect::Count(rest.to_string())
+            }
+            "continue" if rest.is_empty() => Effect::Continue,
+            _ => bail!(
+                "bad effect `{}`, expected one of: log, set, count, continue",
+                input
+            ),
+        };
+
+        Ok(effect)
+    }
+
+    /// Display the effect.
+    pub fn display<'a>(&'a self, device: &'a Device) -> EffectDisplay<'a> {
+        EffectDisplay {
+            effect: self,
+            device,
+        }
+    }
+}
+
+pub struct EffectDisplay<'a> {
+    effect: &'a Effect,
+    device: &'a Device,
+}
+
+impl fmt::Display for EffectDisplay<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registers = &self.device.registers;
+
+        match *self.effect {
+            Effect::Log(ref format) => write!(fmt, "log {}", format.display(registers)),
+            Effect::LogFile(ref format, ref path, _) => {
+                write!(fmt, "log >> {} {}", path, format.display(registers))
+            }
+            Effect::Set(reg, ref expr) => write!(
+                fmt,
+                "set {} {}",
+                registers.name(reg),
+                expr.display(registers)
+            ),
+            Effect::Count(ref name) => write!(fmt, "count {}", name),
+            Effect::Continue => write!(fmt, "continue"),
+        }
+    }
+}
+
+/// A message with expressions in braces, like `c = {c}`.
+pub struct Format {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Expr(Expr),
+}
+
+impl Format {
+    /// Parse a format, optionally in double quotes. Braces are escaped by
+    /// doubling them, like `{{`.
+    pub fn parse(input: &str, device: &Device) -> Result<Format, Error> {
+        let input = input.trim();
+
+        let input = if input.len() >= 2 && input.starts_with('"') && input.ends_with('"') {
+            &input[1..input.len() - 1]
+        } else {
+            input
+        };
+
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut it = input.char_indices().peekable();
+
+        while let Some((n, c)) = it.next() {
+            match c {
+                '{' if it.peek().map(|(_, c)| *c) == Some('{') => {
+                    it.next();
+                    text.push('{');
+                }
+                '}' if it.peek().map(|(_, c)| *c) == Some('}') => {
+                    it.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let end = match input[n..].find('}') {
+                        Some(end) => n + end,
+                        None => bail!("unterminated `{{` in `{}`", input),
+                    };
+
+                    let expr = &input[n + 1..end];
+                    let expr = parser::parse_expr(expr, device)
+                        .map_err(|e| format_err!("bad expression `{{{}}}`: {}", expr, e))?;
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+
+                    parts.push(Part::Expr(expr));
+
+                    while it.peek().map(|(n, _)| *n <= end).unwrap_or(false) {
+                        it.next();
+                    }
+                }
+                '}' => bail!("unmatched `}}` in `{}`", input),
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Format { parts })
+    }
+
+    /// Evaluate the format into a message.
+    pub fn eval(&self, device: &Device) -> Result<String, Error> {
+        let mut out = String::new();
+
+        for part in &self.parts {
+            match *part {
+                Part::Text(ref text) => out.push_str(text),
+                Part::Expr(ref expr) => {
+                    let value: Reg = expr.eval(&device.registers)?;
+                    out.push_str(&value.to_string());
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Display the format, quoted.
+    pub fn display<'a>(&'a self, registers: &'a crate::registers::Registers) -> FormatDisplay<'a> {
+        FormatDisplay {
+            format: self,
+            registers,
+        }
+    }
+}
+
+pub struct FormatDisplay<'a> {
+    format: &'a Format,
+    registers: &'a crate::registers::Registers,
+}
+
+impl fmt::Display for FormatDisplay<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "\"")?;
+
+        for part in &self.format.parts {
+            match *part {
+                Part::Text(ref text) => {
+                    write!(fmt, "{}", text.replace('{', "{{").replace('}', "}}"))?
+                }
+                Part::Expr(ref expr) => write!(fmt, "{{{}}}", expr.display(self.registers))?,
+            }
+        }
+
+        write!(fmt, "\"")
+    }
+}
+
+/// Find the given pattern in the input, skipping anything in double quotes.
+fn find_outside_quotes(input: &str, pattern: &str) -> Option<usize> {
+    let mut quoted = false;
+
+    for (n, c) in input.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+            continue;
+        }
+
+        if !quoted && input[n..].starts_with(pattern) {
+            return Some(n);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Breakpoint, Counters};
+    use crate::{device::Device, hook::Action, Reg};
+
+    #[test]
+    pub fn test_breakpoint_effects() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\n#reg c=7\naddi 0 1 0\naddi 0 1 0\naddi 0 1 0\n".lines())
+            .expect("not load");
+
+        let mut breakpoint = Breakpoint::parse(
+            "line(0) || line(2) do log \"a = {a}; c + 1 = {c + 1} {{x}}\"; set b a * 2; count hits; continue",
+            &device,
+        )
+        .expect("not parse");
+
+        assert_eq!(
+            breakpoint.display(&device).to_string(),
+            "any(line(0), line(2)) do log \"a = {a}; c + 1 = {c + 1} {{x}}\"; set b a * 2; count hits; continue"
+        );
+
+        let mut counters = Counters::new();
+        let mut log = Vec::new();
+
+        for _ in 0..3 {
+            device.step().expect("not step");
+
+            if let Action::Pause = breakpoint.test(&device).expect("not test") {
+                let pause = breakpoint
+                    .fire(&mut device, &mut counters, &mut log)
+                    .expect("not fire");
+                assert!(!pause);
+            }
+        }
+
+        assert_eq!(log, vec!["a = 1; c + 1 = 8 {x}", "a = 3; c + 1 = 8 {x}"]);
+        assert_eq!(counters.get("hits"), Some(&2));
+        assert_eq!(device.registers.iter().nth(1), Some(Reg::from(6)));
+    }
+
+    #[test]
+    pub fn test_trace() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\naddi 0 1 0\n".lines())
+            .expect("not load");
+
+        let breakpoint = Breakpoint::trace("line(0)", &device).expect("not parse");
+        assert_eq!(
+            breakpoint.display(&device).to_string(),
+            "line(0) do log \"ip={ip} a={a} b={b} c={c} d={d} e={e}\"; continue"
+        );
+    }
+
+    #[test]
+    pub fn test_log_file_is_flushed() {
+        let mut device = Device::default();
+        device.load("addi 0 1 0\n".lines()).expect("not load");
+
+        let path = std::env::temp_dir().join(format!("elfdb-log-{}.txt", std::process::id()));
+        let input = format!("line(0) do log >> {} \"a = {{a}}\"", path.display());
+        let mut breakpoint = Breakpoint::parse(&input, &device).expect("not parse");
+
+        device.step().expect("not step");
+        assert!(matches!(breakpoint.test(&device), Ok(Action::Pause)));
+        breakpoint
+            .fire(&mut device, &mut Counters::new(), &mut Vec::new())
+            .expect("not fire");
+        breakpoint.flush().expect("not flush");
+
+        let contents = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).expect("not remove");
+        assert_eq!(contents.expect("not read"), "a = 1\n");
+    }
+
+    #[test]
+    pub fn test_hits_ignore_and_disable() {
+        let mut device = Device::default();
+        device
+            .load("addi 0 1 0\n".repeat(8).lines())
+            .expect("not load");
+
+        let mut breakpoint = Breakpoint::parse("unique(a)", &device).expect("not parse");
+        breakpoint.ignore = 2;
+
+        let mut fired = Vec::new();
+
+        for step in 0..8 {
+            if step == 4 {
+                breakpoint.enabled = false;
+            }
+
+            if step == 6 {
+                breakpoint.enabled = true;
+            }
+
+            device.step().expect("not step");
+
+            if let Action::Pause = breakpoint.test(&device).expect("not test") {
+                fired.push(step);
+            }
+        }
+
+        // NB: values seen while disabled are not hits.
+        assert_eq!(fired, vec![2, 3, 6, 7]);
+        assert_eq!(breakpoint.hits, 6);
+        assert_eq!(breakpoint.ignore, 0);
+    }
+}
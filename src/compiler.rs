@@ -0,0 +1,837 @@
+//! A compiler from a tiny language to ElfCode.
+//!
+//! ```text
+//! #ip f
+//! n = 10;
+//! sum = 0;
+//!
+//! while n > 0 {
+//!     sum = sum + n;
+//!     n = n - 1;
+//! }
+//! ```
+//!
+//! The language supports assignments, `while` and `if` / `else`, and
+//! expressions using `+`, `-`, `*`, `&`, `|`, and the comparisons `==`, `!=`,
+//! `>`, `>=`, `<` and `<=`. Operators bind like they do in Rust, and any
+//! non-zero value is considered true. Comments start with `//`.
+//!
+//! `#ip <reg>` selects the register bound to the instruction pointer, which
+//! defaults to the last register, and `#regs <count>` changes the number of
+//! registers available. They can be given in any order. Every variable is
+//! allocated a register of its own, the remaining registers are used for
+//! temporaries.
+//!
+//! The compiler generates source for the [assembler](crate::assembler), which
+//! takes care of resolving jumps.
+
+use crate::{
+    assembler::{self, Program},
+    op_code::InstructionSet,
+    registers::Registers,
+};
+use failure::{bail, format_err, Error};
+use std::{fmt, path};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Sym(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Token::Ident(ref ident) => ident.fmt(fmt),
+            Token::Number(number) => number.fmt(fmt),
+            Token::Sym(sym) => sym.fmt(fmt),
+        }
+    }
+}
+
+/// Symbols, longest first so that they are matched greedily.
+const SYMBOLS: [&str; 17] = [
+    "==", "!=", ">=", "<=", ">", "<", "=", "+", "-", "*", "&", "|", "(", ")", "{", "}", ";",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl BinOp {
+    fn decode(sym: &str) -> Option<BinOp> {
+        use self::BinOp::*;
+
+        let out = match sym {
+            "+" => Add,
+            "-" => Sub,
+            "*" => Mul,
+            "&" => And,
+            "|" => Or,
+            "==" => Eq,
+            "!=" => Ne,
+            ">" => Gt,
+            ">=" => Ge,
+            "<" => Lt,
+            "<=" => Le,
+            _ => return None,
+        };
+
+        Some(out)
+    }
+
+    /// Binding power of the operator, higher binds tighter.
+    fn precedence(self) -> u32 {
+        use self::BinOp::*;
+
+        match self {
+            Eq | Ne | Gt | Ge | Lt | Le => 1,
+            Or => 2,
+            And => 3,
+            Add | Sub => 4,
+            Mul => 5,
+        }
+    }
+
+    /// If the operator produces either `0` or `1`.
+    fn is_boolean(self) -> bool {
+        use self::BinOp::*;
+
+        matches!(self, Eq | Ne | Gt | Ge | Lt | Le)
+    }
+
+    /// Evaluate the operator on constants, returns `None` on overflow.
+    fn fold(self, a: i64, b: i64) -> Option<i64> {
+        use self::BinOp::*;
+
+        let value = match self {
+            Add => return a.checked_add(b),
+            Sub => return a.checked_sub(b),
+            Mul => return a.checked_mul(b),
+            And => a & b,
+            Or => a | b,
+            Eq => (a == b) as i64,
+            Ne => (a != b) as i64,
+            Gt => (a > b) as i64,
+            Ge => (a >= b) as i64,
+            Lt => (a < b) as i64,
+            Le => (a <= b) as i64,
+        };
+
+        Some(value)
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::BinOp::*;
+
+        let op = match *self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            And => "&",
+            Or => "|",
+            Eq => "==",
+            Ne => "!=",
+            Gt => ">",
+            Ge => ">=",
+            Lt => "<",
+            Le => "<=",
+        };
+
+        op.fmt(fmt)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Var(String),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn is_boolean(&self) -> bool {
+        match *self {
+            Expr::Binary(op, ..) => op.is_boolean(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Expr::Number(number) => number.fmt(fmt),
+            Expr::Var(ref var) => var.fmt(fmt),
+            Expr::Neg(ref inner) => write!(fmt, "-{}", Nested(inner)),
+            Expr::Binary(op, ref a, ref b) => write!(fmt, "{} {} {}", Nested(a), op, Nested(b)),
+        }
+    }
+}
+
+/// Display an expression, with parenthesis if it's not a leaf.
+struct Nested<'a>(&'a Expr);
+
+impl fmt::Display for Nested<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self.0 {
+            Expr::Neg(..) | Expr::Binary(..) => write!(fmt, "({})", self.0),
+            ref expr => expr.fmt(fmt),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Assign(String, Expr),
+    While(Expr, Vec<(usize, Stmt)>),
+    If(Expr, Vec<(usize, Stmt)>, Vec<(usize, Stmt)>),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    /// Line of the current token, used for diagnostics.
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some((line, _)) => *line,
+            None => 0,
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        match self.tokens.get(self.pos).cloned() {
+            Some((_, token)) => {
+                self.pos += 1;
+                Ok(token)
+            }
+            None => bail!("unexpected end of input"),
+        }
+    }
+
+    fn expect(&mut self, sym: &'static str) -> Result<(), Error> {
+        match self.next()? {
+            Token::Sym(s) if s == sym => Ok(()),
+            other => bail!("expected `{}`, but got `{}`", sym, other),
+        }
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        match self.peek() {
+            Some(Token::Sym(s)) => *s == sym,
+            _ => false,
+        }
+    }
+
+    fn is_ident(&self, ident: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(i)) => i == ident,
+            _ => false,
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<(usize, Stmt)>, Error> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+
+        while !self.is_sym("}") {
+            stmts.push(self.stmt()?);
+        }
+
+        self.expect("}")?;
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<(usize, Stmt), Error> {
+        let line = self.line();
+
+        let stmt = match self.next()? {
+            Token::Ident(ref ident) if ident == "while" => {
+                let cond = self.expr(0)?;
+                let body = self.block()?;
+                Stmt::While(cond, body)
+            }
+            Token::Ident(ref ident) if ident == "if" => {
+                let cond = self.expr(0)?;
+                let then = self.block()?;
+
+                let otherwise = if self.is_ident("else") {
+                    self.next()?;
+                    self.block()?
+                } else {
+                    Vec::new()
+                };
+
+                Stmt::If(cond, then, otherwise)
+            }
+            Token::Ident(var) => {
+                self.expect("=")?;
+                let expr = self.expr(0)?;
+                self.expect(";")?;
+                Stmt::Assign(var, expr)
+            }
+            other => bail!("expected statement, but got `{}`", other),
+        };
+
+        Ok((line, stmt))
+    }
+
+    /// Parse an expression where all operators bind tighter than `min`.
+    fn expr(&mut self, min: u32) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+
+        while let Some(Token::Sym(sym)) = self.peek() {
+            let op = match BinOp::decode(sym) {
+                Some(op) if op.precedence() > min => op,
+                _ => break,
+            };
+
+            self.next()?;
+            let rhs = self.expr(op.precedence())?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        match self.next()? {
+            Token::Number(number) => Ok(Expr::Number(number)),
+            Token::Ident(var) => Ok(Expr::Var(var)),
+            Token::Sym("-") => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Token::Sym("(") => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            other => bail!("expected expression, but got `{}`", other),
+        }
+    }
+}
+
+/// A value which is either stored in a register or an immediate.
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Reg(usize),
+    Imm(i64),
+}
+
+struct Compiler {
+    ip: usize,
+    /// Variables and the registers they are allocated to.
+    vars: Vec<(String, usize)>,
+    /// Registers available for temporaries.
+    free: Vec<usize>,
+    /// Generated assembly.
+    out: Vec<String>,
+    /// Counter used to generate unique labels.
+    labels: usize,
+    /// Line of the statement being compiled.
+    line: usize,
+}
+
+impl Compiler {
+    fn label(&mut self) -> String {
+        let label = format!("l{}", self.labels);
+        self.labels += 1;
+        label
+    }
+
+    fn emit(&mut self, op: &str, a: i64, b: i64, c: usize) {
+        self.out.push(format!("    {} {} {} {}", op, a, b, c));
+    }
+
+    fn alloc(&mut self) -> Result<usize, Error> {
+        match self.free.pop() {
+            Some(reg) => Ok(reg),
+            None => bail!("out of registers for temporaries, try simplifying the expression"),
+        }
+    }
+
+    fn release(&mut self, reg: Option<usize>) {
+        self.free.extend(reg);
+    }
+
+    fn var(&self, name: &str) -> usize {
+        match self.vars.iter().find(|(v, _)| v == name) {
+            Some((_, reg)) => *reg,
+            None => panic!("variable not allocated: {}", name),
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[(usize, Stmt)]) -> Result<(), Error> {
+        // NB: the line is only added to errors by the caller, so that nested
+        // statements report the innermost line once.
+        for (line, stmt) in stmts {
+            self.line = *line;
+            self.stmt(stmt)?;
+        }
+
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        match *stmt {
+            Stmt::Assign(ref var, ref expr) => {
+                self.out.push(format!("    ; {} = {}", var, expr));
+                let reg = self.var(var);
+                self.compile_into(expr, reg)?;
+            }
+            Stmt::While(ref cond, ref body) => {
+                let top = self.label();
+                let end = self.label();
+
+                self.out.push(format!("{}: ; while {}", top, cond));
+                self.condition(cond, &end)?;
+                self.stmts(body)?;
+                self.out.push(format!("    jmp {}", top));
+                self.out.push(format!("{}:", end));
+            }
+            Stmt::If(ref cond, ref then, ref otherwise) => {
+                let next = self.label();
+                let end = self.label();
+
+                self.out.push(format!("    ; if {}", cond));
+                self.condition(cond, &next)?;
+                self.stmts(then)?;
+
+                if !otherwise.is_empty() {
+                    self.out.push(format!("    jmp {}", end));
+                }
+
+                self.out.push(format!("{}:", next));
+                self.stmts(otherwise)?;
+                self.out.push(format!("{}:", end));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate the condition, and jump to `otherwise` if it doesn't hold.
+    fn condition(&mut self, cond: &Expr, otherwise: &str) -> Result<(), Error> {
+        let t = self.alloc()?;
+        self.compile_into(cond, t)?;
+
+        if !cond.is_boolean() {
+            // normalize to `0` or `1`.
+            self.emit("eqri", t as i64, 0, t);
+            self.emit("eqri", t as i64, 0, t);
+        }
+
+        let ip = self.ip;
+        self.emit("addr", t as i64, ip as i64, ip);
+        self.out.push(format!("    jmp {}", otherwise));
+        self.release(Some(t));
+        Ok(())
+    }
+
+    /// Get the expression as a value, allocating a temporary if needed.
+    fn operand(&mut self, expr: &Expr) -> Result<(Value, Option<usize>), Error> {
+        match *expr {
+            Expr::Number(number) => Ok((Value::Imm(number), None)),
+            Expr::Var(ref var) => Ok((Value::Reg(self.var(var)), None)),
+            ref expr => {
+                let t = self.alloc()?;
+                self.compile_into(expr, t)?;
+                Ok((Value::Reg(t), Some(t)))
+            }
+        }
+    }
+
+    /// Compile the expression, storing the result in `dst`.
+    fn compile_into(&mut self, expr: &Expr, dst: usize) -> Result<(), Error> {
+        match *expr {
+            Expr::Number(number) => {
+                self.emit("seti", number, 0, dst);
+            }
+            Expr::Var(ref var) => {
+                let reg = self.var(var);
+
+                if reg != dst {
+                    self.emit("setr", reg as i64, 0, dst);
+                }
+            }
+            Expr::Neg(ref inner) => match self.operand(inner)? {
+                (Value::Imm(number), _) => self.emit("seti", -number, 0, dst),
+                (Value::Reg(reg), t) => {
+                    self.emit("muli", reg as i64, -1, dst);
+                    self.release(t);
+                }
+            },
+            Expr::Binary(op, ref a, ref b) => {
+                let (a, ta) = self.operand(a)?;
+                let (b, tb) = self.operand(b)?;
+                self.binary(op, a, b, dst)?;
+                self.release(tb);
+                self.release(ta);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn binary(&mut self, op: BinOp, a: Value, b: Value, dst: usize) -> Result<(), Error> {
+        use self::BinOp::*;
+        use self::Value::*;
+
+        if let (Imm(a), Imm(b)) = (a, b) {
+            let value = match op.fold(a, b) {
+                Some(value) => value,
+                None => bail!("overflow in constant expression: {} {} {}", a, op, b),
+            };
+
+            self.emit("seti", value, 0, dst);
+            return Ok(());
+        }
+
+        match op {
+            Add | Mul | And | Or => {
+                let name = match op {
+                    Add => "add",
+                    Mul => "mul",
+                    And => "ban",
+                    _ => "bor",
+                };
+
+                match (a, b) {
+                    (Reg(a), Reg(b)) => self.emit(&format!("{}r", name), a as i64, b as i64, dst),
+                    (Reg(a), Imm(b)) | (Imm(b), Reg(a)) => {
+                        self.emit(&format!("{}i", name), a as i64, b, dst)
+                    }
+                    (Imm(..), Imm(..)) => unreachable!(),
+                }
+            }
+            Sub => match (a, b) {
+                (Reg(a), Imm(b)) => self.emit("addi", a as i64, -b, dst),
+                (Imm(a), Reg(b)) => {
+                    self.emit("muli", b as i64, -1, dst);
+                    self.emit("addi", dst as i64, a, dst);
+                }
+                (Reg(a), Reg(b)) => {
+                    // NB: `dst` can't be used for the negation if it's also the first operand.
+                    let t = if a == dst { Some(self.alloc()?) } else { None };
+                    let n = t.unwrap_or(dst);
+                    self.emit("muli", b as i64, -1, n);
+                    self.emit("addr", a as i64, n as i64, dst);
+                    self.release(t);
+                }
+                (Imm(..), Imm(..)) => unreachable!(),
+            },
+            Eq => self.compare("eq", a, b, dst),
+            Gt => self.compare("gt", a, b, dst),
+            Lt => self.compare("gt", b, a, dst),
+            Ne => {
+                self.compare("eq", a, b, dst);
+                self.emit("eqri", dst as i64, 0, dst);
+            }
+            Ge => {
+                self.compare("gt", b, a, dst);
+                self.emit("eqri", dst as i64, 0, dst);
+            }
+            Le => {
+                self.compare("gt", a, b, dst);
+                self.emit("eqri", dst as i64, 0, dst);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compare(&mut self, name: &str, a: Value, b: Value, dst: usize) {
+        use self::Value::*;
+
+        match (a, b) {
+            (Reg(a), Reg(b)) => self.emit(&format!("{}rr", name), a as i64, b as i64, dst),
+            (Reg(a), Imm(b)) => self.emit(&format!("{}ri", name), a as i64, b, dst),
+            (Imm(a), Reg(b)) => self.emit(&format!("{}ir", name), a, b as i64, dst),
+            (Imm(..), Imm(..)) => unreachable!(),
+        }
+    }
+}
+
+/// Tokenize a single line, appending the tokens to `out`.
+fn tokenize(n: usize, line: &str, out: &mut Vec<(usize, Token)>) -> Result<(), Error> {
+    let line = match line.find("//") {
+        Some(index) => &line[..index],
+        None => line,
+    };
+
+    let mut rest = line.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            'a'..='z' | '_' => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'))
+                    .unwrap_or(rest.len());
+                out.push((n, Token::Ident(rest[..len].to_string())));
+                len
+            }
+            '0'..='9' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let number = str::parse(&rest[..len])
+                    .map_err(|e| format_err!("line {}: bad number: {}", n, e))?;
+                out.push((n, Token::Number(number)));
+                len
+            }
+            _ => match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(sym) => {
+                    out.push((n, Token::Sym(sym)));
+                    sym.len()
+                }
+                None => bail!("line {}: unexpected character: {}", n, c),
+            },
+        };
+
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(())
+}
+
+/// Collect variables in the order in which they appear.
+fn collect_vars(stmts: &[(usize, Stmt)], vars: &mut Vec<String>) {
+    fn expr(e: &Expr, vars: &mut Vec<String>) {
+        match *e {
+            Expr::Number(..) => {}
+            Expr::Var(ref var) => add(var, vars),
+            Expr::Neg(ref inner) => expr(inner, vars),
+            Expr::Binary(_, ref a, ref b) => {
+                expr(a, vars);
+                expr(b, vars);
+            }
+        }
+    }
+
+    fn add(var: &str, vars: &mut Vec<String>) {
+        if !vars.iter().any(|v| v == var) {
+            vars.push(var.to_string());
+        }
+    }
+
+    for (_, stmt) in stmts {
+        match *stmt {
+            Stmt::Assign(ref var, ref e) => {
+                add(var, vars);
+                expr(e, vars);
+            }
+            Stmt::While(ref cond, ref body) => {
+                expr(cond, vars);
+                collect_vars(body, vars);
+            }
+            Stmt::If(ref cond, ref then, ref otherwise) => {
+                expr(cond, vars);
+                collect_vars(then, vars);
+                collect_vars(otherwise, vars);
+            }
+        }
+    }
+}
+
+/// Compile the given source into assembly for the [assembler](crate::assembler).
+pub fn compile_to_assembly<'a>(input: impl Iterator<Item = &'a str>) -> Result<String, Error> {
+    let mut registers = Registers::default();
+    let mut ip = None;
+    let mut tokens = Vec::new();
+
+    for (n, line) in input.enumerate() {
+        // NB: comments are stripped so that directives can have them too.
+        let line = match line.find("//") {
+            Some(index) => &line[..index],
+            None => line,
+        };
+
+        let line = line.trim();
+
+        // NB: `#ip` is resolved once all lines are read, so that it can come
+        // before `#regs`.
+        if let Some(reg) = line.strip_prefix("#ip") {
+            ip = Some((n + 1, reg.trim()));
+            continue;
+        }
+
+        if let Some(len) = line.strip_prefix("#regs") {
+            let len = str::parse(len.trim())
+                .map_err(|e| format_err!("line {}: bad argument to `#regs`: {}", n + 1, e))?;
+
+            registers
+                .resize(len)
+                .map_err(|e| format_err!("line {}: {}", n + 1, e))?;
+            continue;
+        }
+
+        tokenize(n + 1, line, &mut tokens)?;
+    }
+
+    let ip = match ip {
+        Some((n, reg)) => match (registers.lookup(reg), str::parse::<usize>(reg)) {
+            (Some(reg), _) => Some(reg),
+            (None, Ok(reg)) if reg < registers.len() => Some(reg),
+            _ => bail!("line {}: bad register in `#ip`: {}", n, reg),
+        },
+        None => None,
+    };
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut stmts = Vec::new();
+
+    while parser.peek().is_some() {
+        match parser.stmt() {
+            Ok(stmt) => stmts.push(stmt),
+            Err(e) => bail!("line {}: {}", parser.line(), e),
+        }
+    }
+
+    let mut names = Vec::new();
+    collect_vars(&stmts, &mut names);
+
+    for name in &names {
+        if registers.lookup(name).is_some() || ["while", "if", "else"].contains(&name.as_str()) {
+            bail!("`{}` is reserved and can't be used as a variable", name);
+        }
+    }
+
+    let ip = ip.unwrap_or(registers.len() - 1);
+    let mut available = (0..registers.len()).filter(|r| *r != ip);
+    let mut vars = Vec::new();
+
+    for name in names {
+        match available.next() {
+            Some(reg) => vars.push((name, reg)),
+            None => bail!(
+                "too many variables, only {} are available",
+                registers.len() - 1
+            ),
+        }
+    }
+
+    // NB: reversed so that temporaries are allocated in ascending order.
+    let free = available.rev().collect();
+
+    let mut compiler = Compiler {
+        ip,
+        vars,
+        free,
+        out: Vec::new(),
+        labels: 0,
+        line: 0,
+    };
+
+    compiler
+        .stmts(&stmts)
+        .map_err(|e| format_err!("line {}: {}", compiler.line, e))?;
+
+    let mut out = String::new();
+    out.push_str(&format!("#regs {}\n", registers.len()));
+    out.push_str(&format!("#ip {}\n", ip));
+
+    for (name, reg) in &compiler.vars {
+        out.push_str(&format!("#name {} {}\n", reg, name));
+    }
+
+    for line in compiler.out {
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+/// Compile the given source into a program.
+pub fn compile<'a>(input: impl Iterator<Item = &'a str>) -> Result<Program, Error> {
+    let assembly = compile_to_assembly(input)?;
+    assembler::assemble(&InstructionSet::default(), assembly.lines())
+}
+
+/// Compile the program at the given path.
+pub fn compile_path(path: impl AsRef<path::Path>) -> Result<Program, Error> {
+    use std::{fs::File, io::Read};
+
+    let mut f = File::open(path.as_ref())?;
+    let mut input = String::new();
+    f.read_to_string(&mut input)?;
+    compile(input.lines())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile;
+    use crate::{device::Device, Reg};
+
+    #[test]
+    pub fn test_compile() {
+        let source = "
+            #ip f
+            n = 10;
+            sum = 0;
+
+            // sum up all odd numbers.
+            while n > 0 {
+                if n & 1 == 1 {
+                    sum = sum + n * 2 - n;
+                }
+
+                n = n - 1;
+            }
+        ";
+
+        let program = compile(source.lines()).expect("not compile");
+
+        let mut device = Device::default();
+        device.load_program(program).expect("not load");
+
+        while !device.halted {
+            device.step().expect("not step");
+        }
+
+        assert_eq!(device.registers.lookup("sum"), Some(1));
+        assert_eq!(
+            device.registers.iter().nth(1),
+            Some(Reg::from(1 + 3 + 5 + 7 + 9))
+        );
+    }
+
+    #[test]
+    pub fn test_directive_order_and_errors() {
+        let source = "#ip g\n#regs 8\nn = 1;\nwhile n > 0 {\n    n = 9223372036854775807 + 1;\n}\n";
+
+        let error = compile(source.lines()).expect_err("overflow");
+        let error = error.to_string();
+        assert!(error.starts_with("line 5: overflow"), "{}", error);
+        assert_eq!(error.matches("line").count(), 1, "{}", error);
+
+        let program = compile("#ip g\n#regs 8\nn = 1;\n".lines()).expect("not compile");
+        assert_eq!(program.ip, Some(6));
+        assert_eq!(program.registers, 8);
+
+        let source = "#ip f // instruction pointer\n#regs 8 // more room\nn = 1; // n\n";
+        let program = compile(source.lines()).expect("not compile");
+        assert_eq!(program.ip, Some(5));
+        assert_eq!(program.registers, 8);
+    }
+}
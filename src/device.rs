@@ -1,19 +1,56 @@
-use crate::{instruction::Instruction, registers::Registers};
+use crate::{
+    assembler::Program,
+    bits::BitSet,
+    instruction::Instruction,
+    op_code::{InstructionSet, OpCode, OpCodeMap},
+    registers::Registers,
+};
 use failure::{bail, format_err, Error};
-use hashbrown::HashSet;
-use std::path;
+use std::{fmt, path};
+
+/// A fault which caused the device to halt.
+#[derive(Debug, Clone)]
+pub struct Fault {
+    /// The line of the instruction which caused the fault, if known.
+    pub line: Option<usize>,
+    /// Description of the fault.
+    pub message: String,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(fmt, "fault on line {}: {}", line, self.message),
+            None => write!(fmt, "fault: {}", self.message),
+        }
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct Device {
     /// If the device is halted.
     pub halted: bool,
+    /// The fault which halted the device, if any.
+    pub fault: Option<Fault>,
     /// Loaded instructions.
     pub instructions: Vec<Instruction>,
+    /// Instruction set used to decode instructions, which custom operations
+    /// are registered in.
+    pub instruction_set: InstructionSet,
+    /// Instruction set selected with `#isa` by the loaded program, which is
+    /// used instead of `instruction_set` until the next program is loaded.
+    program_set: Option<InstructionSet>,
+    /// Mapping used to decode instructions with numeric op codes.
+    pub op_code_map: OpCodeMap,
+    /// Mappings added with `#opcode` and `#opcodes` by the loaded program,
+    /// which take precedence over `op_code_map` until the next program is
+    /// loaded.
+    program_op_codes: OpCodeMap,
     pub registers: Registers,
     /// Count of number of instructions that has been executed.
     pub count: usize,
     /// Unique instructions that has been run.
-    pub unique: HashSet<usize>,
+    pub unique: BitSet,
 }
 
 impl Device {
@@ -21,44 +58,238 @@ impl Device {
     pub fn load_path(&mut self, path: impl AsRef<path::Path>) -> Result<(), Error> {
         use std::{fs::File, io::Read};
 
-        let mut f = File::open(path.as_ref())?;
+        let path = path.as_ref();
+        let mut f = File::open(path)?;
         let mut input = String::new();
         f.read_to_string(&mut input)?;
-        self.load(input.lines())
+        self.load_relative(input.lines(), path.parent())
     }
 
     /// Load a program.
+    ///
+    /// Blank lines are ignored, and comments starting with `;` or `//` are kept
+    /// together with the instruction that they belong to.
+    ///
+    /// Instructions can also be written with a numeric op code, like
+    /// `9 2 1 2`, which is decoded using `op_code_map`.
     pub fn load<'a>(&mut self, input: impl Iterator<Item = &'a str>) -> Result<(), Error> {
+        self.load_relative(input, None)
+    }
+
+    /// Load a program, where paths in directives are relative to the given
+    /// directory.
+    fn load_relative<'a>(
+        &mut self,
+        input: impl Iterator<Item = &'a str>,
+        directory: Option<&path::Path>,
+    ) -> Result<(), Error> {
         self.reset();
         self.instructions.clear();
+        self.registers.unload();
+        self.program_set = None;
+        self.program_op_codes = OpCodeMap::default();
+
+        // comments on lines of their own, waiting for an instruction.
+        let mut comments = Vec::new();
 
         for line in input {
-            if line.starts_with("#ip") {
-                let ip = line
-                    .split(" ")
-                    .nth(1)
+            let (code, comment) = split_comment(line);
+            let code = code.trim();
+
+            if code.is_empty() {
+                comments.extend(comment);
+                continue;
+            }
+
+            if code.starts_with('#') {
+                self.directive(code, directory)?;
+                continue;
+            }
+
+            let mut inst = if is_numeric(code) {
+                self.decode_numeric(code)?
+            } else {
+                match Instruction::decode(self.decoding_set(), code) {
+                    Some(inst) => inst,
+                    None => {
+                        bail!("bad instruction: {}", line);
+                    }
+                }
+            };
+
+            comments.extend(comment);
+
+            if !comments.is_empty() {
+                inst.comment = Some(comments.join("; "));
+                comments.clear();
+            }
+
+            self.instructions.push(inst);
+        }
+
+        if let Some(ip) = self.registers.ip {
+            if ip >= self.registers.len() {
+                bail!("no such register in `#ip`: {}", ip);
+            }
+        }
+
+        self.registers.forget_removed_formats();
+        self.reset();
+        Ok(())
+    }
+
+    /// Load an assembled program.
+    pub fn load_program(&mut self, program: Program) -> Result<(), Error> {
+        self.reset();
+        self.registers.unload();
+        self.program_set = None;
+        self.program_op_codes = OpCodeMap::default();
+        self.registers.resize(program.registers)?;
+        self.registers.ip = program.ip;
+
+        for (reg, alias) in &program.aliases {
+            self.registers.set_alias(*reg, alias)?;
+        }
+
+        for (reg, value) in &program.initial {
+            self.registers.set_initial(*reg, value.clone())?;
+        }
+
+        self.instructions = program.instructions;
+        self.registers.forget_removed_formats();
+        self.reset();
+        Ok(())
+    }
+
+    /// The instruction set used to decode the program being loaded.
+    pub fn decoding_set(&self) -> &InstructionSet {
+        self.program_set.as_ref().unwrap_or(&self.instruction_set)
+    }
+
+    /// Mappings of numeric op codes added by the loaded program.
+    pub fn program_op_codes(&self) -> &OpCodeMap {
+        &self.program_op_codes
+    }
+
+    /// Get the op code mapped to the given id, preferring mappings added by
+    /// the loaded program.
+    pub fn op_code(&self, id: i64) -> Option<OpCode> {
+        self.program_op_codes
+            .get(id)
+            .or_else(|| self.op_code_map.get(id))
+    }
+
+    /// Decode an instruction with a numeric op code, like `9 2 1 2`.
+    fn decode_numeric(&self, code: &str) -> Result<Instruction, Error> {
+        for map in &[&self.program_op_codes, &self.op_code_map] {
+            if let Some(inst) = Instruction::decode_numeric(map, code) {
+                return Ok(inst);
+            }
+        }
+
+        let id = code.split_whitespace().next().unwrap_or_default();
+
+        match str::parse::<i64>(id) {
+            Ok(id) if self.op_code(id).is_none() => bail!(
+                "unknown op code `{}`, map it with `#opcode {} <name>`",
+                id,
+                id
+            ),
+            _ => bail!("bad instruction: {}", code),
+        }
+    }
+
+    /// Process a single directive, like `#ip 1`.
+    fn directive(&mut self, line: &str, directory: Option<&path::Path>) -> Result<(), Error> {
+        let mut it = line.split_whitespace();
+
+        match it.next() {
+            Some("#ip") => {
+                let ip = it
+                    .next()
                     .ok_or_else(|| format_err!("expected argument to `#ip`"))
                     .and_then(|arg| {
                         str::parse(arg).map_err(|e| format_err!("bad argument to `#ip`: {}", e))
                     })?;
 
-                self.registers.ip = ip;
-                continue;
+                self.registers.ip = Some(ip);
+            }
+            Some("#regs") => {
+                let len = it
+                    .next()
+                    .ok_or_else(|| format_err!("expected argument to `#regs`"))
+                    .and_then(|arg| {
+                        str::parse(arg).map_err(|e| format_err!("bad argument to `#regs`: {}", e))
+                    })?;
+
+                self.registers.resize(len)?;
+            }
+            Some("#reg") => {
+                self.registers.parse_initial(it)?;
             }
+            Some("#name") => {
+                let (name, alias) = match (it.next(), it.next()) {
+                    (Some(name), Some(alias)) => (name, alias),
+                    _ => bail!("expected: #name <reg> <alias>"),
+                };
 
-            let inst = match Instruction::decode(line) {
-                Some(inst) => inst,
-                None => {
-                    bail!("bad instruction: {}", line);
+                let reg = match self.registers.lookup(name) {
+                    Some(reg) => reg,
+                    None => bail!("bad register in `#name`: {}", name),
+                };
+
+                self.registers.set_alias(reg, alias)?;
+            }
+            Some("#opcode") => {
+                let (id, name) = match (it.next(), it.next()) {
+                    (Some(id), Some(name)) => (id, name),
+                    _ => bail!("expected: #opcode <id> <name>"),
+                };
+
+                let set = self.program_set.as_ref().unwrap_or(&self.instruction_set);
+                self.program_op_codes.insert_named(set, id, name)?;
+            }
+            Some("#opcodes") => {
+                let path = it
+                    .next()
+                    .ok_or_else(|| format_err!("expected argument to `#opcodes`"))?;
+
+                let full = match directory {
+                    Some(directory) => directory.join(path),
+                    None => path::PathBuf::from(path),
+                };
+
+                self.program_op_codes = OpCodeMap::load_path(self.decoding_set(), &full)
+                    .map_err(|e| format_err!("failed to load `{}`: {}", full.display(), e))?;
+            }
+            Some("#isa") => {
+                let name = it
+                    .next()
+                    .ok_or_else(|| format_err!("expected argument to `#isa`"))?;
+
+                let mut set = match InstructionSet::by_name(name) {
+                    Some(set) => set,
+                    None => bail!("no such instruction set: {}", name),
+                };
+
+                // NB: keep custom operations which have been registered.
+                for op_code in self.instruction_set.iter().filter(|o| !o.is_builtin()) {
+                    set.register(op_code);
                 }
-            };
 
-            self.instructions.push(inst);
+                self.program_set = Some(set);
+            }
+            Some(other) => bail!("unsupported directive: {}", other),
+            None => bail!("expected directive"),
         }
 
         Ok(())
     }
 
+    /// Step the device a single instruction.
+    ///
+    /// If the instruction fails, the device is halted and the reason is
+    /// recorded in `fault`.
     pub fn step(&mut self) -> Result<(), Error> {
         let Device {
             ref instructions,
@@ -66,25 +297,50 @@ impl Device {
             ..
         } = *self;
 
-        let ip = registers.ip()?;
+        let ip = match registers.ip() {
+            Ok(ip) => ip,
+            Err(e) => {
+                self.raise(None, e);
+                return Ok(());
+            }
+        };
 
         let inst = match instructions.get(ip) {
             Some(inst) => inst,
             None => {
+                // NB: no instruction ran, so hooks shouldn't see the last one.
+                registers.last_ip = None;
                 self.halted = true;
                 return Ok(());
             }
         };
 
-        registers.last_ip = Some(registers.ip()?);
-        inst.op_code.apply(registers, &inst.inputs, inst.output)?;
-        *registers.ip_mut()? += 1;
+        registers.last_ip = Some(ip);
+
+        let result = inst
+            .op_code
+            .apply(registers, &inst.inputs, inst.output)
+            .and_then(|_| registers.advance_ip());
+
+        if let Err(e) = result {
+            self.raise(Some(ip), e);
+            return Ok(());
+        }
 
         self.unique.insert(ip);
         self.count += 1;
         Ok(())
     }
 
+    /// Halt the device because of a fault.
+    fn raise(&mut self, line: Option<usize>, error: Error) {
+        self.halted = true;
+        self.fault = Some(Fault {
+            line,
+            message: error.to_string(),
+        });
+    }
+
     /// Clear all temporary state for the device.
     ///
     /// Temporary state keeps track of things that has been modified.
@@ -94,8 +350,183 @@ impl Device {
 
     pub fn reset(&mut self) {
         self.halted = false;
+        self.fault = None;
         self.count = 0;
         self.unique.clear();
         self.registers.reset();
     }
 }
+
+/// Test if the given code is an instruction with a numeric op code.
+fn is_numeric(code: &str) -> bool {
+    code.chars()
+        .next()
+        .map(|c| c.is_ascii_digit() || c == '-')
+        .unwrap_or(false)
+}
+
+/// Split a line into its code and its comment, if present.
+pub(crate) fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let start = match (line.find(';'), line.find("//")) {
+        (Some(a), Some(b)) => usize::min(a, b),
+        (Some(a), None) | (None, Some(a)) => a,
+        (None, None) => return (line, None),
+    };
+
+    let (code, comment) = line.split_at(start);
+    let comment = comment.trim_start_matches(&[';', '/'][..]).trim();
+
+    if comment.is_empty() {
+        return (code, None);
+    }
+
+    (code, Some(comment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Device;
+    use crate::Reg;
+
+    #[test]
+    pub fn test_load_comments_and_directives() {
+        let mut device = Device::default();
+
+        let program =
+            "#ip 1\n#reg a=1 c=5\n#name c counter\n\n; setup\nseti 5 0 2 // five\naddi 2 1 2\n";
+        device.load(program.lines()).expect("not load");
+
+        assert_eq!(device.instructions.len(), 2);
+        assert_eq!(
+            device.instructions[0].comment.as_deref(),
+            Some("setup; five")
+        );
+        assert!(device.instructions[1].comment.is_none());
+        assert_eq!(device.registers.lookup("counter"), Some(2));
+        assert_eq!(device.registers.name(2).to_string(), "counter");
+        assert_eq!(
+            device.registers.iter().collect::<Vec<_>>(),
+            vec![1, 0, 5, 0, 0, 0]
+                .into_iter()
+                .map(Reg::from)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    pub fn test_load_regs() {
+        let mut device = Device::default();
+
+        let program = "#regs 8\n#ip 7\n#reg g=4\naddi 6 1 6\n";
+        device.load(program.lines()).expect("not load");
+
+        assert_eq!(device.registers.len(), 8);
+        assert_eq!(device.registers.ip, Some(7));
+        assert_eq!(device.registers.name(6).to_string(), "g");
+
+        device.step().expect("not step");
+        assert_eq!(device.registers.iter().nth(6), Some(Reg::from(5)));
+
+        assert!(device.load("#regs 8\n#ip 7\n#regs 6\n".lines()).is_err());
+        assert!(device.load("#ip 6\naddi 0 1 0\n".lines()).is_err());
+    }
+
+    #[test]
+    pub fn test_fault() {
+        use crate::arithmetic::Arithmetic;
+
+        let program = "#ip 5\n#reg a=9223372036854775807\naddi 0 1 0\n";
+
+        let mut device = Device::default();
+        device.load(program.lines()).expect("not load");
+        device.step().expect("not step");
+
+        assert!(device.halted);
+        let fault = device.fault.as_ref().expect("no fault");
+        assert_eq!(fault.line, Some(0));
+
+        let mut device = Device::default();
+        device
+            .registers
+            .set_arithmetic(Arithmetic::Big)
+            .expect("not set arithmetic");
+        device.load(program.lines()).expect("not load");
+        device.step().expect("not step");
+
+        assert!(device.fault.is_none());
+        assert_eq!(
+            device.registers.iter().next().map(|r| r.to_string()),
+            Some(String::from("9223372036854775808"))
+        );
+    }
+
+    #[test]
+    pub fn test_formats_are_kept() {
+        use crate::format::Format;
+
+        let mut device = Device::default();
+        device.registers.set_format(1, Format::Hex);
+        device.registers.set_format(7, Format::Binary);
+
+        for _ in 0..2 {
+            device
+                .load("#regs 8\naddi 0 1 0\n".lines())
+                .expect("not load");
+            assert_eq!(device.registers.format(1), Format::Hex);
+            assert_eq!(device.registers.format(7), Format::Binary);
+        }
+
+        // registers which no longer exist forget their format.
+        device.load("addi 0 1 0\n".lines()).expect("not load");
+        device.registers.resize(8).expect("not resize");
+        assert_eq!(device.registers.format(7), Format::Decimal);
+
+        device.registers.set_format(7, Format::Binary);
+        device.registers.resize(6).expect("not resize");
+        device.registers.resize(8).expect("not resize");
+        assert_eq!(device.registers.format(7), Format::Decimal);
+
+        device.registers.set_default_format(Format::Bits);
+        device.load("addi 0 1 0\n".lines()).expect("not load");
+        assert_eq!(device.registers.format(1), Format::Bits);
+    }
+
+    #[test]
+    pub fn test_load_numeric() {
+        let mut device = Device::default();
+
+        let program = "#regs 4\n#opcode 9 seti\n#opcode 3 mulr\n9 7 0 1\n3 1 1 2\n";
+        device.load(program.lines()).expect("not load");
+
+        assert_eq!(device.registers.ip, None);
+        assert_eq!(device.instructions[1].display().to_string(), "mulr 1 1 2");
+
+        while !device.halted {
+            device.step().expect("not step");
+        }
+
+        assert!(device.fault.is_none());
+        assert_eq!(device.count, 2);
+        assert_eq!(device.registers.iter().nth(2), Some(Reg::from(49)));
+
+        let mut device = Device::default();
+        assert!(device.load("#opcode 9 seti\n4 1 2 3\n".lines()).is_err());
+
+        // mappings from one program don't leak into the next.
+        device.load(program.lines()).expect("not load");
+        assert!(device.load("#regs 4\n9 7 0 1\n".lines()).is_err());
+        assert!(device.program_op_codes().is_empty());
+
+        // `#opcodes` is relative to the program.
+        let dir = std::env::temp_dir().join(format!("elfcode-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("not create dir");
+        std::fs::write(dir.join("codes.txt"), "9 seti\n").expect("not write");
+        std::fs::write(dir.join("program.txt"), "#opcodes codes.txt\n9 7 0 1\n")
+            .expect("not write");
+
+        let result = device.load_path(dir.join("program.txt"));
+        std::fs::remove_dir_all(&dir).expect("not remove dir");
+        result.expect("not load");
+        assert_eq!(device.instructions[0].display().to_string(), "seti 7 0 1");
+    }
+}
@@ -0,0 +1,149 @@
+//! Value expressions used in breakpoint conditions, like `c % 3 == 0`.
+
+use crate::{arithmetic, hook, registers::Registers, Reg};
+use failure::Error;
+use std::fmt;
+
+/// An expression which evaluates to a value.
+///
+/// Comparisons and `!` evaluate to `1` if they hold, and `0` otherwise.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// A constant.
+    Number(Reg),
+    /// The value of a register.
+    Register(usize),
+    /// The instruction pointer, also when it's not bound to a register.
+    Ip,
+    /// Negate a value, like `-a`.
+    Neg(Box<Expr>),
+    /// Logical not, like `!a`.
+    Not(Box<Expr>),
+    /// An arithmetic operation, like `a + 1`.
+    Arithmetic(arithmetic::Op, Box<Expr>, Box<Expr>),
+    /// A comparison, like `a < b`.
+    Compare(hook::Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluate the expression, using the arithmetic semantics of the given
+    /// registers.
+    pub fn eval(&self, registers: &Registers) -> Result<Reg, Error> {
+        let arithmetic = registers.arithmetic();
+
+        let value = match *self {
+            Expr::Number(ref value) => value.clone(),
+            Expr::Register(reg) => registers.peek(reg)?.clone(),
+            Expr::Ip => Reg::from(registers.ip()? as i64),
+            Expr::Neg(ref value) => {
+                let value = value.eval(registers)?;
+                arithmetic.eval(arithmetic::Op::Sub, &Reg::default(), &value)?
+            }
+            Expr::Not(ref value) => Reg::from(value.eval(registers)?.is_zero()),
+            Expr::Arithmetic(op, ref a, ref b) => {
+                arithmetic.eval(op, &a.eval(registers)?, &b.eval(registers)?)?
+            }
+            Expr::Compare(op, ref a, ref b) => {
+                Reg::from(op.test(&a.eval(registers)?, &b.eval(registers)?))
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Test if the expression holds, which is when it evaluates to anything
+    /// but zero.
+    pub fn test(&self, registers: &Registers) -> Result<bool, Error> {
+        Ok(!self.eval(registers)?.is_zero())
+    }
+
+    /// Get the value of the expression if it doesn't depend on any registers.
+    pub fn constant(&self, registers: &Registers) -> Option<Reg> {
+        match *self {
+            Expr::Register(..) | Expr::Ip => None,
+            Expr::Number(ref value) => Some(value.clone()),
+            Expr::Neg(ref value) | Expr::Not(ref value) => {
+                value.constant(registers)?;
+                self.eval(registers).ok()
+            }
+            Expr::Arithmetic(_, ref a, ref b) | Expr::Compare(_, ref a, ref b) => {
+                a.constant(registers)?;
+                b.constant(registers)?;
+                self.eval(registers).ok()
+            }
+        }
+    }
+
+    /// Display the expression, using the names of registers.
+    pub fn display<'a>(&'a self, registers: &'a Registers) -> Display<'a> {
+        Display {
+            expr: self,
+            registers,
+        }
+    }
+
+    /// The precedence of the expression, higher binds tighter.
+    fn precedence(&self) -> u32 {
+        use crate::arithmetic::Op::*;
+
+        match *self {
+            Expr::Compare(..) => 1,
+            Expr::Arithmetic(Or, ..) => 2,
+            Expr::Arithmetic(And, ..) => 3,
+            Expr::Arithmetic(Add, ..) | Expr::Arithmetic(Sub, ..) => 4,
+            Expr::Arithmetic(..) => 5,
+            Expr::Neg(..) | Expr::Not(..) => 6,
+            Expr::Number(..) | Expr::Register(..) | Expr::Ip => 7,
+        }
+    }
+}
+
+pub struct Display<'a> {
+    expr: &'a Expr,
+    registers: &'a Registers,
+}
+
+impl Display<'_> {
+    /// Display a sub-expression, wrapping it in parenthesis if it binds less
+    /// tightly than `precedence`.
+    fn child(&self, fmt: &mut fmt::Formatter<'_>, expr: &Expr, precedence: u32) -> fmt::Result {
+        let display = expr.display(self.registers);
+
+        if expr.precedence() < precedence {
+            write!(fmt, "({})", display)
+        } else {
+            write!(fmt, "{}", display)
+        }
+    }
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precedence = self.expr.precedence();
+
+        match *self.expr {
+            Expr::Number(ref value) => write!(fmt, "{}", value),
+            Expr::Register(reg) => write!(fmt, "{}", self.registers.name(reg)),
+            Expr::Ip => write!(fmt, "ip"),
+            Expr::Neg(ref value) => {
+                write!(fmt, "-")?;
+                self.child(fmt, value, precedence)
+            }
+            Expr::Not(ref value) => {
+                write!(fmt, "!")?;
+                self.child(fmt, value, precedence)
+            }
+            Expr::Arithmetic(op, ref a, ref b) => {
+                self.child(fmt, a, precedence)?;
+                write!(fmt, " {} ", op)?;
+                // NB: operators are left-associative.
+                self.child(fmt, b, precedence + 1)
+            }
+            Expr::Compare(op, ref a, ref b) => {
+                self.child(fmt, a, precedence + 1)?;
+                write!(fmt, " {} ", op.symbol())?;
+                self.child(fmt, b, precedence + 1)
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Static control flow of programs.
+
+use crate::{device::Device, instruction::Jump};
+
+/// A jump from one line of a program to another, which is known without
+/// running the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    /// The line the jump is made from.
+    pub from: usize,
+    /// The line the jump is made to.
+    pub to: usize,
+    /// If the jump is only made when a condition holds.
+    pub conditional: bool,
+}
+
+/// Find all jumps in the loaded program whose targets are known ahead of
+/// time.
+///
+/// Jumps past the end of the program, which halt it, are not included.
+pub fn edges(device: &Device) -> Vec<Edge> {
+    let instructions = &device.instructions;
+    let mut edges = Vec::new();
+
+    for (line, inst) in instructions.iter().enumerate() {
+        let previous = line.checked_sub(1).and_then(|l| instructions.get(l));
+
+        let (to, conditional) = match inst.jump(&device.registers, Some(line), previous) {
+            Some(Jump::Goto(to)) => (to, false),
+            Some(Jump::Skip) => (line + 2, true),
+            _ => continue,
+        };
+
+        if to == line + 1 || to >= instructions.len() {
+            continue;
+        }
+
+        edges.push(Edge {
+            from: line,
+            to,
+            conditional,
+        });
+    }
+
+    edges
+}
+
+/// Draw a gutter of arrows between the sources and targets of jumps, with
+/// one string for each of the `len` lines of a program.
+///
+/// Every jump is drawn in a lane of its own, and at most `lanes` lanes are
+/// used. Jumps which don't fit are left out, preferring to draw short jumps.
+pub fn gutter(edges: &[Edge], len: usize, lanes: usize) -> Vec<String> {
+    let mut sorted = edges.to_vec();
+    sorted.sort_by_key(|e| (span(e).1 - span(e).0, e.from));
+
+    // lanes are numbered from the inside out, and hold the ranges occupied in
+    // them.
+    let mut occupied: Vec<Vec<(usize, usize)>> = Vec::new();
+    let mut placed = Vec::new();
+
+    for edge in sorted {
+        let (lo, hi) = span(&edge);
+
+        let free = occupied
+            .iter()
+            .position(|ranges| ranges.iter().all(|&(l, h)| hi < l || h < lo));
+
+        let lane = match free {
+            Some(lane) => lane,
+            None if occupied.len() < lanes => {
+                occupied.push(Vec::new());
+                occupied.len() - 1
+            }
+            None => continue,
+        };
+
+        occupied[lane].push((lo, hi));
+        placed.push((lane, edge));
+    }
+
+    let width = occupied.len();
+
+    if width == 0 {
+        return vec![String::new(); len];
+    }
+
+    // NB: the last column holds arrow heads.
+    let mut grid = vec![vec![' '; width + 1]; len];
+
+    for &(lane, edge) in &placed {
+        let (lo, hi) = span(&edge);
+        let column = width - 1 - lane;
+
+        if lo == hi {
+            continue;
+        }
+
+        grid[lo][column] = '┌';
+        grid[hi][column] = '└';
+
+        for row in grid.iter_mut().take(hi).skip(lo + 1) {
+            row[column] = '│';
+        }
+    }
+
+    for &(lane, edge) in &placed {
+        let column = width - 1 - lane;
+
+        for &line in &[edge.from, edge.to] {
+            for c in &mut grid[line][column + 1..width] {
+                *c = match *c {
+                    '│' => '┼',
+                    '┌' => '┬',
+                    '└' => '┴',
+                    ' ' => '─',
+                    c => c,
+                };
+            }
+        }
+
+        let source = &mut grid[edge.from][width];
+
+        if *source == ' ' {
+            *source = '─';
+        }
+
+        grid[edge.to][width] = if edge.from == edge.to { '↺' } else { '►' };
+    }
+
+    grid.into_iter()
+        .map(|row| row.into_iter().collect())
+        .collect()
+}
+
+/// The lowest and highest line covered by a jump.
+fn span(edge: &Edge) -> (usize, usize) {
+    if edge.from < edge.to {
+        (edge.from, edge.to)
+    } else {
+        (edge.to, edge.from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edges, gutter, Edge};
+    use crate::device::Device;
+
+    #[test]
+    pub fn test_edges_and_gutter() {
+        let mut device = Device::default();
+
+        let program = "#ip 5\n\
+                       addi 0 1 0\n\
+                       eqrr 0 1 2\n\
+                       addr 2 5 5\n\
+                       seti 0 0 5\n\
+                       mulr 5 5 5\n";
+
+        device.load(program.lines()).expect("not load");
+
+        let edges = edges(&device);
+
+        assert_eq!(
+            edges,
+            vec![
+                Edge {
+                    from: 2,
+                    to: 4,
+                    conditional: true,
+                },
+                Edge {
+                    from: 3,
+                    to: 1,
+                    conditional: false,
+                },
+            ]
+        );
+
+        let gutter = gutter(&edges, device.instructions.len(), 4);
+
+        assert_eq!(
+            gutter,
+            vec!["   ", "┌─►", "│┌─", "└┼─", " └►"]
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        );
+    }
+}
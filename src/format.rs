@@ -0,0 +1,164 @@
+//! Formats used to display register values.
+
+use crate::Reg;
+use num_bigint::{BigInt, Sign};
+use std::fmt;
+
+/// A format to display values in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Signed decimal, like `-42`.
+    #[default]
+    Decimal,
+    /// Unsigned decimal, where negative values are shown in two's complement
+    /// using the smallest multiple of 64 bits which holds them, like
+    /// `18446744073709551574`.
+    Unsigned,
+    /// Hexadecimal, like `0x2a`.
+    Hex,
+    /// Binary, like `0b101010`.
+    Binary,
+    /// Binary in groups of eight bits, like `0b1_00101010`, which makes masks
+    /// used with `bani` and `bori` easy to read.
+    Bits,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            Format::Decimal => "dec",
+            Format::Unsigned => "unsigned",
+            Format::Hex => "hex",
+            Format::Binary => "bin",
+            Format::Bits => "bits",
+        };
+
+        name.fmt(fmt)
+    }
+}
+
+impl Format {
+    /// Get a format by name.
+    pub fn by_name(name: &str) -> Option<Format> {
+        let out = match name {
+            "dec" => Format::Decimal,
+            "unsigned" => Format::Unsigned,
+            "hex" => Format::Hex,
+            "bin" => Format::Binary,
+            "bits" => Format::Bits,
+            _ => return None,
+        };
+
+        Some(out)
+    }
+
+    /// Names of all available formats.
+    pub fn names() -> &'static [&'static str] {
+        &["dec", "unsigned", "hex", "bin", "bits"]
+    }
+
+    /// The format which follows this one, used to cycle through formats.
+    pub fn next(self) -> Format {
+        match self {
+            Format::Decimal => Format::Unsigned,
+            Format::Unsigned => Format::Hex,
+            Format::Hex => Format::Binary,
+            Format::Binary => Format::Bits,
+            Format::Bits => Format::Decimal,
+        }
+    }
+
+    /// Display a value using this format.
+    pub fn display<'a>(self, value: &'a Reg) -> Display<'a> {
+        Display {
+            format: self,
+            value,
+        }
+    }
+}
+
+pub struct Display<'a> {
+    format: Format,
+    value: &'a Reg,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Format::Unsigned, Reg::Small(value)) = (self.format, self.value) {
+            return write!(fmt, "{}", *value as u64);
+        }
+
+        // NB: negative values are written with a sign, instead of in two's
+        // complement, so that small and big values look the same.
+        let value = self.value.to_big();
+
+        let (sign, magnitude) = match value.sign() {
+            Sign::Minus => ("-", -value),
+            _ => ("", value),
+        };
+
+        match self.format {
+            Format::Unsigned if sign == "-" => {
+                // NB: in two's complement, using the smallest multiple of 64
+                // bits which holds the value.
+                let bits = (&magnitude - BigInt::from(1)).bits() + 1;
+                let width = bits.div_ceil(64) * 64;
+                write!(fmt, "{}", (BigInt::from(1) << width) - magnitude)
+            }
+            Format::Decimal | Format::Unsigned => write!(fmt, "{}{}", sign, magnitude),
+            Format::Hex => write!(fmt, "{}{:#x}", sign, magnitude),
+            Format::Binary => write!(fmt, "{}{:#b}", sign, magnitude),
+            Format::Bits => {
+                let bits = format!("{:b}", magnitude);
+                write!(fmt, "{}0b", sign)?;
+
+                for (i, c) in bits.chars().enumerate() {
+                    if i > 0 && (bits.len() - i) % 8 == 0 {
+                        write!(fmt, "_")?;
+                    }
+
+                    write!(fmt, "{}", c)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Format;
+    use crate::Reg;
+    use num_bigint::BigInt;
+
+    #[test]
+    pub fn test_format() {
+        let value = Reg::from(42);
+        assert_eq!(Format::Decimal.display(&value).to_string(), "42");
+        assert_eq!(Format::Hex.display(&value).to_string(), "0x2a");
+        assert_eq!(Format::Binary.display(&value).to_string(), "0b101010");
+
+        let value = Reg::from(-42);
+        assert_eq!(Format::Hex.display(&value).to_string(), "-0x2a");
+        assert_eq!(
+            Format::Unsigned.display(&value).to_string(),
+            "18446744073709551574"
+        );
+
+        let value = Reg::big(-BigInt::from(42));
+        assert_eq!(
+            Format::Unsigned.display(&value).to_string(),
+            "18446744073709551574"
+        );
+
+        let value = Reg::big(-(BigInt::from(1) << 64u32));
+        assert_eq!(
+            Format::Unsigned.display(&value).to_string(),
+            "340282366920938463444927863358058659840"
+        );
+
+        let value = Reg::from(0x1_2a);
+        assert_eq!(Format::Bits.display(&value).to_string(), "0b1_00101010");
+    }
+}
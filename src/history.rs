@@ -0,0 +1,206 @@
+//! Bounded history of register values.
+
+use crate::{device::Device, Reg};
+use num_traits::ToPrimitive as _;
+use std::collections::VecDeque;
+
+/// Characters used to draw sparklines, from lowest to highest.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A value recorded in the history of a register.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// The step count when the value was recorded.
+    pub step: usize,
+    /// The value of the register.
+    pub value: Reg,
+}
+
+/// The history of values for every register, keeping at most `capacity`
+/// values per register.
+#[derive(Debug, Clone)]
+pub struct History {
+    capacity: usize,
+    registers: Vec<VecDeque<Sample>>,
+}
+
+impl History {
+    /// Construct a new history, keeping at most `capacity` values per
+    /// register.
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            registers: Vec::new(),
+        }
+    }
+
+    /// Record the value of every register which changed since it was last
+    /// recorded.
+    pub fn record(&mut self, device: &Device) {
+        let len = device.registers.len();
+
+        if self.registers.len() != len {
+            self.registers.resize_with(len, VecDeque::new);
+        }
+
+        for (reg, samples) in self.registers.iter_mut().enumerate() {
+            let value = match device.registers.peek(reg) {
+                Ok(value) => value,
+                Err(..) => continue,
+            };
+
+            if samples.back().map(|s| s.value == *value).unwrap_or(false) {
+                continue;
+            }
+
+            if samples.len() == self.capacity {
+                samples.pop_front();
+            }
+
+            samples.push_back(Sample {
+                step: device.count,
+                value: value.clone(),
+            });
+        }
+    }
+
+    /// Record the value of every register written by the last step, used
+    /// while tracing so that the step of every write is known.
+    pub fn record_writes(&mut self, device: &Device) {
+        let len = device.registers.len();
+
+        if self.registers.len() != len {
+            self.registers.resize_with(len, VecDeque::new);
+        }
+
+        for (reg, samples) in self.registers.iter_mut().enumerate() {
+            if !device.registers.is_written(reg) {
+                continue;
+            }
+
+            let value = match device.registers.peek(reg) {
+                Ok(value) => value,
+                Err(..) => continue,
+            };
+
+            // NB: the same step might be recorded more than once.
+            if samples
+                .back()
+                .map(|s| s.step == device.count)
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            if samples.len() == self.capacity {
+                samples.pop_front();
+            }
+
+            samples.push_back(Sample {
+                step: device.count,
+                value: value.clone(),
+            });
+        }
+    }
+
+    /// Get the recorded values of a register, from oldest to newest.
+    pub fn get(&self, reg: usize) -> impl DoubleEndedIterator<Item = &Sample> + '_ {
+        self.registers.get(reg).into_iter().flat_map(|s| s.iter())
+    }
+
+    /// Clear the history.
+    pub fn clear(&mut self) {
+        self.registers.clear();
+    }
+
+    /// Draw a sparkline of the last `width` values of a register, scaled
+    /// between the smallest and largest of them.
+    pub fn sparkline(&self, reg: usize, width: usize) -> String {
+        let values = self
+            .get(reg)
+            .rev()
+            .take(width)
+            .map(|s| s.value.to_big().to_f64().unwrap_or(0f64))
+            .collect::<Vec<_>>();
+
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+        values
+            .iter()
+            .rev()
+            .map(|v| {
+                if max > min {
+                    let n = ((v - min) / (max - min) * (SPARKS.len() - 1) as f64).round();
+                    SPARKS[n as usize]
+                } else {
+                    SPARKS[0]
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::{device::Device, Reg};
+
+    #[test]
+    pub fn test_history() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\naddi 2 1 2\nseti -1 0 5\n".lines())
+            .expect("not load");
+
+        let mut history = History::new(3);
+        history.record(&device);
+
+        for _ in 0..8 {
+            device.step().expect("not step");
+            history.record(&device);
+        }
+
+        let values = history
+            .get(2)
+            .map(|s| (s.step, s.value.clone()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![(3, Reg::from(2)), (5, Reg::from(3)), (7, Reg::from(4))]
+        );
+
+        assert_eq!(history.sparkline(2, 8), "▁▅█");
+        assert_eq!(history.get(0).count(), 1);
+    }
+
+    #[test]
+    pub fn test_record_writes() {
+        let mut device = Device::default();
+        device
+            .load("addi 0 1 0\naddi 0 0 0\nseti 7 0 1\n".lines())
+            .expect("not load");
+
+        let mut history = History::new(8);
+
+        for _ in 0..3 {
+            device.clear();
+            device.step().expect("not step");
+            history.record_writes(&device);
+            history.record_writes(&device);
+        }
+
+        let values = |reg| {
+            history
+                .get(reg)
+                .map(|s| (s.step, s.value.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        // NB: writes of the same value are recorded too.
+        assert_eq!(values(0), vec![(1, Reg::from(1)), (2, Reg::from(1))]);
+        assert_eq!(values(1), vec![(3, Reg::from(7))]);
+        assert_eq!(history.get(2).count(), 0);
+    }
+}
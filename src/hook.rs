@@ -1,9 +1,9 @@
-use crate::{device::Device, Reg};
+use crate::{device::Device, expr, op_code::OpCode, Reg};
 use failure::Error;
 use hashbrown::HashSet;
-use std::fmt;
+use std::{fmt, ops};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
     Pause,
     None,
@@ -12,6 +12,7 @@ pub enum Action {
 #[derive(Debug, Clone, Copy)]
 pub enum Op {
     Eq,
+    Ne,
     Lt,
     Lte,
     Gt,
@@ -19,11 +20,12 @@ pub enum Op {
 }
 
 impl Op {
-    pub fn test(self, a: Reg, b: Reg) -> bool {
+    pub fn test(self, a: &Reg, b: &Reg) -> bool {
         use self::Op::*;
 
         match self {
             Eq => a == b,
+            Ne => a != b,
             Gt => a > b,
             Gte => a >= b,
             Lt => a < b,
@@ -32,12 +34,29 @@ impl Op {
     }
 }
 
+impl Op {
+    /// The symbol used for the operation in infix expressions, like `==`.
+    pub fn symbol(self) -> &'static str {
+        use self::Op::*;
+
+        match self {
+            Eq => "==",
+            Ne => "!=",
+            Gt => ">",
+            Gte => ">=",
+            Lt => "<",
+            Lte => "<=",
+        }
+    }
+}
+
 impl fmt::Display for Op {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         use self::Op::*;
 
         let op = match self {
             Eq => "eq",
+            Ne => "ne",
             Gt => "gt",
             Gte => "gte",
             Lt => "lt",
@@ -48,43 +67,170 @@ impl fmt::Display for Op {
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Hook {
+/// A condition which decides when a breakpoint fires.
+///
+/// Implement this to add your own conditions, and wrap them in a [`Hook`]
+/// using [`Hook::new`]. All built-in conditions are implemented on top of it.
+///
+/// Conditions should only inspect the device through untracked accessors,
+/// like [`Registers::peek`], so that testing them never affects which
+/// registers are tracked as read or written.
+///
+/// [`Registers::peek`]: crate::registers::Registers::peek
+pub trait Condition {
+    /// Test if the condition holds.
+    fn test(&mut self, device: &Device) -> Result<Action, Error>;
+
+    /// Write the condition the way it's written in a breakpoint, like
+    /// `line(28)`.
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    /// Reset any state of the condition.
+    fn reset(&mut self) {}
+
+    /// Write the state of the condition.
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "no state")
+    }
+
+    /// Explain why the condition held, defaults to how it's written.
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(device, fmt)
+    }
+}
+
+/// A breakpoint condition.
+pub struct Hook {
+    condition: Box<dyn Condition>,
+}
+
+// NB: conditions need a device to be displayed, so only their state is shown.
+impl fmt::Debug for Hook {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_tuple("Hook")
+            .field(&format_args!("{}", self.inspect()))
+            .finish()
+    }
+}
+
+impl<C> From<C> for Hook
+where
+    C: 'static + Condition,
+{
+    fn from(condition: C) -> Hook {
+        Hook::new(condition)
+    }
+}
+
+impl Hook {
+    /// Wrap a custom condition.
+    pub fn new(condition: impl 'static + Condition) -> Hook {
+        Hook {
+            condition: Box::new(condition),
+        }
+    }
+
     /// Break when the given register is read.
-    Read(usize),
+    pub fn read(register: usize) -> Hook {
+        Hook::new(Read(register))
+    }
+
     /// Break when the given register is written to.
-    Write(usize),
+    pub fn write(register: usize) -> Hook {
+        Hook::new(Write(register))
+    }
+
     /// Break when the given line is run.
-    Line(usize),
-    /// Break when the given register equals the specified value.
-    Op(Op, usize, Reg),
-    /// Break when a unique value has been observed in the specified registry.
-    Unique(HashSet<Reg>, Option<Reg>, usize),
-    /// Break when the inverted condition of a hook is true.
-    Not(Box<Hook>),
-    /// All the criterias listed must match.
-    All(Vec<Hook>),
-}
+    pub fn line(line: usize) -> Hook {
+        Hook::new(Line(line))
+    }
+
+    /// Break when any line in the given range is run.
+    pub fn line_range(range: ops::Range<usize>) -> Hook {
+        Hook::new(LineRange(range))
+    }
+
+    /// Break before the given line is run.
+    pub fn before(line: usize) -> Hook {
+        Hook::new(Before(line))
+    }
+
+    /// Break when an instruction with the given op code is run.
+    pub fn op_code(op_code: OpCode) -> Hook {
+        Hook::new(Executed(op_code))
+    }
+
+    /// Break when the instruction which ran didn't continue with the next
+    /// line.
+    pub fn jump() -> Hook {
+        Hook::new(Jump)
+    }
+
+    /// Break when the value of the given register changes.
+    pub fn changed(register: usize) -> Hook {
+        Hook::new(Changed {
+            last: None,
+            previous: None,
+            register,
+        })
+    }
+
+    /// Break when the device halts, either because the program finished or
+    /// because of a fault.
+    pub fn halt() -> Hook {
+        Hook::new(Halt { seen: false })
+    }
+
+    /// Break when the device halts because of a fault.
+    pub fn fault() -> Hook {
+        Hook::new(Fault { seen: false })
+    }
+
+    /// Break once the number of instructions run reaches the given threshold.
+    pub fn steps(threshold: usize) -> Hook {
+        Hook::new(Steps {
+            threshold,
+            passed: false,
+        })
+    }
+
+    /// Break when the comparison between a register and a value holds.
+    pub fn compare(op: Op, register: usize, value: Reg) -> Hook {
+        Hook::new(Compare(op, register, value))
+    }
 
-impl Hook {
     /// Create a Unique hook for the given register.
     pub fn unique(register: usize) -> Hook {
-        Hook::Unique(HashSet::new(), None, register)
+        Hook::new(Unique {
+            seen: HashSet::new(),
+            last: None,
+            register,
+        })
+    }
+
+    /// Break when the inverted condition of a hook is true.
+    pub fn negate(hook: Hook) -> Hook {
+        Hook::new(Not(hook))
+    }
+
+    /// All the criterias listed must match.
+    pub fn all(hooks: Vec<Hook>) -> Hook {
+        Hook::new(All(hooks))
+    }
+
+    /// Any of the criterias listed must match.
+    pub fn any(hooks: Vec<Hook>) -> Hook {
+        Hook::new(Any { hooks, fired: None })
+    }
+
+    /// Break when the expression evaluates to anything but zero.
+    pub fn expr(expr: expr::Expr) -> Hook {
+        Hook::new(Expr(expr))
     }
 
     /// Reset the state of a hook.
     pub fn reset(&mut self) {
-        use self::Hook::*;
-
-        match *self {
-            Unique(ref mut seen, ..) => {
-                seen.clear();
-            }
-            Not(ref mut inner) => {
-                inner.reset();
-            }
-            _ => {}
-        }
+        self.condition.reset();
     }
 
     pub fn inspect<'a>(&'a self) -> Inspect<'a> {
@@ -92,60 +238,16 @@ impl Hook {
     }
 
     /// Test if the breakpoint is valid.
-    pub fn test(&mut self, device: &mut Device) -> Result<Action, Error> {
-        use self::Hook::*;
-
-        match *self {
-            Read(reg) => {
-                if device.registers.is_read(reg) {
-                    return Ok(Action::Pause);
-                }
-            }
-            Write(reg) => {
-                if device.registers.is_written(reg) {
-                    return Ok(Action::Pause);
-                }
-            }
-            Line(line) => {
-                if device
-                    .registers
-                    .last_ip
-                    .as_ref()
-                    .map(|ip| *ip == line)
-                    .unwrap_or(false)
-                {
-                    return Ok(Action::Pause);
-                }
-            }
-            Op(op, reg, value) => {
-                if op.test(device.registers.reg(reg)?, value) {
-                    return Ok(Action::Pause);
-                }
-            }
-            Unique(ref mut seen, ref mut last, register) => {
-                let value = device.registers.reg(register)?;
-
-                if seen.insert(value) {
-                    *last = Some(value);
-                    return Ok(Action::Pause);
-                }
-            }
-            Not(ref mut inner) => match inner.test(device)? {
-                Action::None => return Ok(Action::Pause),
-                Action::Pause => return Ok(Action::None),
-            },
-            All(ref mut hooks) => {
-                for h in hooks {
-                    if let Action::None = h.test(device)? {
-                        return Ok(Action::None);
-                    }
-                }
-
-                return Ok(Action::Pause);
-            }
-        }
+    ///
+    /// Hooks only inspect the device, so testing them never affects which
+    /// registers are tracked as read or written.
+    pub fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        self.condition.test(device)
+    }
 
-        Ok(Action::None)
+    /// Explain why the hook fired, like `unique(c) new value 12345`.
+    pub fn reason<'a>(&'a self, device: &'a Device) -> Reason<'a> {
+        Reason { hook: self, device }
     }
 
     /// Convert hook into a string.
@@ -163,78 +265,870 @@ pub struct Inspect<'a> {
 
 impl fmt::Display for Inspect<'_> {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use self::Hook::*;
+        self.hook.condition.inspect(fmt)
+    }
+}
+
+pub struct Reason<'a> {
+    hook: &'a Hook,
+    device: &'a Device,
+}
 
-        let Inspect { ref hook } = *self;
+impl fmt::Display for Reason<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.hook.condition.reason(self.device, fmt)
+    }
+}
+
+pub struct Display<'a> {
+    hook: &'a Hook,
+    device: &'a Device,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.hook.condition.display(self.device, fmt)
+    }
+}
 
-        match *hook {
-            Read(..) => write!(fmt, "read()"),
-            Write(..) => write!(fmt, "write()"),
-            Line(..) => write!(fmt, "line()"),
-            Op(op, ..) => write!(fmt, "{}()", op),
-            Unique(ref seen, ref last, ..) => {
-                write!(fmt, "unique(seen: {}, last: {:?})", seen.len(), last)
+/// Convert a condition into an action.
+fn pause_if(condition: bool) -> Action {
+    if condition {
+        Action::Pause
+    } else {
+        Action::None
+    }
+}
+
+/// Write the value of a register, or `?` if it doesn't exist.
+fn value(device: &Device, reg: usize) -> String {
+    match device.registers.peek(reg) {
+        Ok(value) => value.to_string(),
+        Err(..) => String::from("?"),
+    }
+}
+
+/// Write a list of hooks, like `all(line(1), read(a))`.
+fn list(
+    fmt: &mut fmt::Formatter<'_>,
+    name: &str,
+    hooks: &[Hook],
+    mut f: impl FnMut(&Hook, &mut fmt::Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    let mut it = hooks.iter().peekable();
+
+    write!(fmt, "{}(", name)?;
+
+    while let Some(h) = it.next() {
+        f(h, fmt)?;
+
+        if it.peek().is_some() {
+            write!(fmt, ", ")?;
+        }
+    }
+
+    write!(fmt, ")")
+}
+
+/// Break when the given register is read.
+pub struct Read(pub usize);
+
+impl Condition for Read {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        Ok(pause_if(device.registers.is_read(self.0)))
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "read({})", device.registers.name(self.0))
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "read()")
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Read(reg) = *self;
+        let name = device.registers.name(reg);
+        write!(fmt, "read({}) value {}", name, value(device, reg))
+    }
+}
+
+/// Break when the given register is written to.
+pub struct Write(pub usize);
+
+impl Condition for Write {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        Ok(pause_if(device.registers.is_written(self.0)))
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "write({})", device.registers.name(self.0))
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "write()")
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Write(reg) = *self;
+        let name = device.registers.name(reg);
+        write!(fmt, "write({}) new value {}", name, value(device, reg))
+    }
+}
+
+/// Break when the given line is run.
+pub struct Line(pub usize);
+
+impl Condition for Line {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        Ok(pause_if(device.registers.last_ip == Some(self.0)))
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "line({})", self.0)
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "line()")
+    }
+}
+
+/// Break when any line in the given range is run.
+pub struct LineRange(pub ops::Range<usize>);
+
+impl Condition for LineRange {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        let ran = match device.registers.last_ip {
+            Some(ip) => self.0.contains(&ip),
+            None => false,
+        };
+
+        Ok(pause_if(ran))
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "line({}..{})", self.0.start, self.0.end)
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "line()")
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(device, fmt)?;
+
+        if let Some(ip) = device.registers.last_ip {
+            write!(fmt, " ran line {}", ip)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Break when an instruction with the given op code is run.
+pub struct Executed(pub OpCode);
+
+impl Condition for Executed {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        let ran = device
+            .registers
+            .last_ip
+            .and_then(|ip| device.instructions.get(ip))
+            .map(|inst| inst.op_code == self.0)
+            .unwrap_or(false);
+
+        Ok(pause_if(ran))
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "opcode({})", self.0)
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "opcode()")
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(device, fmt)?;
+
+        if let Some(ip) = device.registers.last_ip {
+            write!(fmt, " on line {}", ip)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Break when the instruction which ran didn't continue with the next line.
+pub struct Jump;
+
+impl Condition for Jump {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        let jumped = match device.registers.last_ip {
+            Some(last) => device.registers.ip().ok() != Some(last + 1),
+            None => false,
+        };
+
+        Ok(pause_if(jumped))
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "jump()")
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "jump()")
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(device, fmt)?;
+
+        if let (Some(last), Ok(ip)) = (device.registers.last_ip, device.registers.ip()) {
+            write!(fmt, " from line {} to {}", last, ip)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Break when the value of the given register changes, unlike [`Write`]
+/// which also fires when the same value is written.
+pub struct Changed {
+    /// The value the last time the condition was tested.
+    last: Option<Reg>,
+    /// The value before the last change.
+    previous: Option<Reg>,
+    register: usize,
+}
+
+impl Condition for Changed {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        let value = device.registers.peek(self.register)?;
+
+        match self.last {
+            Some(ref last) if last == value => Ok(Action::None),
+            // NB: the first value seen is only remembered.
+            None => {
+                self.last = Some(value.clone());
+                Ok(Action::None)
+            }
+            Some(..) => {
+                self.previous = self.last.replace(value.clone());
+                Ok(Action::Pause)
             }
-            Not(ref inner) => write!(fmt, "not({})", inner.inspect()),
-            All(hooks) => {
-                let mut it = hooks.iter().peekable();
+        }
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "changed({})", device.registers.name(self.register))
+    }
 
-                write!(fmt, "all(")?;
+    fn reset(&mut self) {
+        self.last = None;
+        self.previous = None;
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "changed(last: {:?})", self.last)
+    }
 
-                while let Some(h) = it.next() {
-                    h.inspect().fmt(fmt)?;
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(device, fmt)?;
 
-                    if it.peek().is_some() {
-                        write!(fmt, ", ")?;
-                    }
-                }
+        if let (Some(previous), Some(last)) = (self.previous.as_ref(), self.last.as_ref()) {
+            write!(fmt, " from {} to {}", previous, last)?;
+        }
 
-                write!(fmt, ")")?;
-                Ok(())
+        Ok(())
+    }
+}
+
+/// Break once the number of instructions run reaches the given threshold.
+pub struct Steps {
+    threshold: usize,
+    /// If the threshold has been passed.
+    passed: bool,
+}
+
+impl Condition for Steps {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        if self.passed || device.count < self.threshold {
+            return Ok(Action::None);
+        }
+
+        self.passed = true;
+        Ok(Action::Pause)
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "steps({})", self.threshold)
+    }
+
+    fn reset(&mut self) {
+        self.passed = false;
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "steps(passed: {})", self.passed)
+    }
+}
+
+/// Break when the device halts, either because the program finished or
+/// because of a fault.
+pub struct Halt {
+    /// If the halt has been seen, so that it only fires once.
+    seen: bool,
+}
+
+impl Condition for Halt {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        if self.seen || !device.halted {
+            return Ok(Action::None);
+        }
+
+        self.seen = true;
+        Ok(Action::Pause)
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "halt()")
+    }
+
+    fn reset(&mut self) {
+        self.seen = false;
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "halt(seen: {})", self.seen)
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match device.fault.as_ref() {
+            Some(fault) => write!(fmt, "halt() because of a {}", fault),
+            None => write!(fmt, "halt() program finished"),
+        }
+    }
+}
+
+/// Break when the device halts because of a fault.
+pub struct Fault {
+    /// If the fault has been seen, so that it only fires once.
+    seen: bool,
+}
+
+impl Condition for Fault {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        if self.seen || device.fault.is_none() {
+            return Ok(Action::None);
+        }
+
+        self.seen = true;
+        Ok(Action::Pause)
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "fault()")
+    }
+
+    fn reset(&mut self) {
+        self.seen = false;
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "fault(seen: {})", self.seen)
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match device.fault.as_ref() {
+            Some(fault) => write!(fmt, "fault() {}", fault),
+            None => write!(fmt, "fault()"),
+        }
+    }
+}
+
+/// Break before the given line is run.
+///
+/// Hooks are only tested after a step, so this never fires for the first
+/// line when a program starts.
+pub struct Before(pub usize);
+
+impl Condition for Before {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        Ok(pause_if(
+            !device.halted && device.registers.ip().ok() == Some(self.0),
+        ))
+    }
+
+    fn display(&self, _: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "before({})", self.0)
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "before()")
+    }
+}
+
+/// Break when the comparison between a register and a value holds.
+pub struct Compare(pub Op, pub usize, pub Reg);
+
+impl Condition for Compare {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        let Compare(op, reg, ref value) = *self;
+        Ok(pause_if(op.test(device.registers.peek(reg)?, value)))
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Compare(op, reg, ref value) = *self;
+        write!(fmt, "{}({}, {})", op, device.registers.name(reg), value)
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}()", self.0)
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reg = self.1;
+        self.display(device, fmt)?;
+        let name = device.registers.name(reg);
+        write!(fmt, " with {} = {}", name, value(device, reg))
+    }
+}
+
+/// Break when a unique value has been observed in the specified registry.
+pub struct Unique {
+    seen: HashSet<Reg>,
+    last: Option<Reg>,
+    register: usize,
+}
+
+impl Condition for Unique {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        let value = device.registers.peek(self.register)?;
+
+        if self.seen.contains(value) {
+            return Ok(Action::None);
+        }
+
+        self.seen.insert(value.clone());
+        self.last = Some(value.clone());
+        Ok(Action::Pause)
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "unique({})", device.registers.name(self.register))
+    }
+
+    fn reset(&mut self) {
+        self.seen.clear();
+        self.last = None;
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "unique(seen: {}, last: {:?})",
+            self.seen.len(),
+            self.last
+        )
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(device, fmt)?;
+
+        if let Some(last) = self.last.as_ref() {
+            write!(fmt, " new value {}", last)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Break when the inverted condition of a hook is true.
+pub struct Not(pub Hook);
+
+impl Condition for Not {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        match self.0.test(device)? {
+            Action::None => Ok(Action::Pause),
+            Action::Pause => Ok(Action::None),
+        }
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "not({})", self.0.display(device))
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "not({})", self.0.inspect())
+    }
+}
+
+/// All the criterias listed must match.
+pub struct All(pub Vec<Hook>);
+
+impl Condition for All {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        for h in &mut self.0 {
+            if let Action::None = h.test(device)? {
+                return Ok(Action::None);
+            }
+        }
+
+        Ok(Action::Pause)
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        list(fmt, "all", &self.0, |h, fmt| {
+            write!(fmt, "{}", h.display(device))
+        })
+    }
+
+    fn reset(&mut self) {
+        for h in &mut self.0 {
+            h.reset();
+        }
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        list(fmt, "all", &self.0, |h, fmt| write!(fmt, "{}", h.inspect()))
+    }
+
+    // NB: every hook has to hold, so all of them contributed.
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut it = self.0.iter().peekable();
+
+        while let Some(h) = it.next() {
+            write!(fmt, "{}", h.reason(device))?;
+
+            if it.peek().is_some() {
+                write!(fmt, " and ")?;
             }
         }
+
+        Ok(())
     }
 }
 
-pub struct Display<'a> {
-    hook: &'a Hook,
-    device: &'a Device,
+/// Any of the criterias listed must match.
+pub struct Any {
+    hooks: Vec<Hook>,
+    /// The hook which fired the last time the condition was tested.
+    fired: Option<usize>,
 }
 
-impl fmt::Display for Display<'_> {
-    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use self::Hook::*;
-
-        let Display {
-            ref hook,
-            ref device,
-        } = *self;
-
-        match *hook {
-            Read(reg) => write!(fmt, "read({})", device.registers.name(*reg)),
-            Write(reg) => write!(fmt, "write({})", device.registers.name(*reg)),
-            Line(line) => write!(fmt, "line({})", line),
-            Op(op, reg, value) => {
-                write!(fmt, "{}({}, {})", op, device.registers.name(*reg), *value)
+impl Condition for Any {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        self.fired = None;
+
+        for (index, h) in self.hooks.iter_mut().enumerate() {
+            if let Action::Pause = h.test(device)? {
+                self.fired = Some(index);
+                return Ok(Action::Pause);
             }
-            Unique(_, _, reg) => write!(fmt, "unique({})", device.registers.name(*reg)),
-            Not(ref inner) => write!(fmt, "not({})", inner.display(device)),
-            All(hooks) => {
-                let mut it = hooks.iter().peekable();
+        }
+
+        Ok(Action::None)
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        list(fmt, "any", &self.hooks, |h, fmt| {
+            write!(fmt, "{}", h.display(device))
+        })
+    }
+
+    fn reset(&mut self) {
+        self.fired = None;
+
+        for h in &mut self.hooks {
+            h.reset();
+        }
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        list(fmt, "any", &self.hooks, |h, fmt| {
+            write!(fmt, "{}", h.inspect())
+        })
+    }
+
+    fn reason(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fired.and_then(|index| self.hooks.get(index)) {
+            Some(h) => write!(fmt, "{}", h.reason(device)),
+            None => self.display(device, fmt),
+        }
+    }
+}
+
+/// Break when the expression evaluates to anything but zero.
+pub struct Expr(pub expr::Expr);
+
+impl Condition for Expr {
+    fn test(&mut self, device: &Device) -> Result<Action, Error> {
+        Ok(pause_if(self.0.test(&device.registers)?))
+    }
+
+    fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}", self.0.display(&device.registers))
+    }
+
+    fn inspect(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "expr()")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Hook, Op};
+    use crate::{device::Device, Reg};
+
+    /// Snapshot which registers are tracked as read and written.
+    fn tracking(device: &Device) -> Vec<(bool, bool)> {
+        (0..device.registers.len())
+            .map(|reg| {
+                (
+                    device.registers.is_read(reg),
+                    device.registers.is_written(reg),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_hooks_do_not_track() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\nseti 5 0 2\naddi 0 1 1\n".lines())
+            .expect("not load");
+        device.step().expect("not step");
+
+        let before = tracking(&device);
+
+        let mut hooks = vec![
+            Hook::compare(Op::Eq, 2, Reg::from(5)),
+            Hook::unique(3),
+            Hook::negate(Hook::compare(Op::Gt, 4, Reg::from(1))),
+            Hook::all(vec![Hook::line(0), Hook::compare(Op::Lt, 1, Reg::from(10))]),
+        ];
+
+        for hook in &mut hooks {
+            assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+        }
+
+        assert_eq!(tracking(&device), before);
+        assert_eq!(Hook::read(2).test(&device).ok(), Some(Action::None));
+        assert_eq!(Hook::read(3).test(&device).ok(), Some(Action::None));
+    }
+
+    #[test]
+    pub fn test_before() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\nseti 5 0 2\naddi 0 1 1\n".lines())
+            .expect("not load");
 
-                write!(fmt, "all(")?;
+        let mut hook = Hook::before(1);
+        assert_eq!(hook.test(&device).ok(), Some(Action::None));
 
-                while let Some(h) = it.next() {
-                    h.display(device).fmt(fmt)?;
+        device.step().expect("not step");
+        assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+        // the line hasn't run yet.
+        assert_eq!(device.registers.iter().nth(1), Some(Reg::from(0)));
+
+        device.step().expect("not step");
+        assert_eq!(hook.test(&device).ok(), Some(Action::None));
+    }
 
-                    if it.peek().is_some() {
-                        write!(fmt, ", ")?;
-                    }
-                }
+    #[test]
+    pub fn test_reason() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\nseti 5 0 2\naddi 2 1 2\n".lines())
+            .expect("not load");
+        device.step().expect("not step");
 
-                write!(fmt, ")")?;
-                Ok(())
+        let mut hook = Hook::all(vec![Hook::line(0), Hook::unique(2)]);
+        assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+        assert_eq!(
+            hook.reason(&device).to_string(),
+            "line(0) and unique(c) new value 5"
+        );
+
+        let mut hook = Hook::any(vec![Hook::line(1), Hook::unique(2)]);
+        assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+        assert_eq!(hook.reason(&device).to_string(), "unique(c) new value 5");
+
+        let hook = Hook::compare(Op::Gt, 2, Reg::from(1));
+        assert_eq!(hook.reason(&device).to_string(), "gt(c, 1) with c = 5");
+    }
+
+    #[test]
+    pub fn test_custom_condition() {
+        use super::Condition;
+        use failure::Error;
+        use std::fmt;
+
+        /// Break when the given register is prime.
+        struct Prime(usize);
+
+        impl Condition for Prime {
+            fn test(&mut self, device: &Device) -> Result<Action, Error> {
+                let n = device.registers.peek(self.0)?.as_i64().unwrap_or(0);
+                let prime = n > 1 && (2..n).take_while(|d| d * d <= n).all(|d| n % d != 0);
+                Ok(if prime { Action::Pause } else { Action::None })
             }
+
+            fn display(&self, device: &Device, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(fmt, "prime({})", device.registers.name(self.0))
+            }
+        }
+
+        let mut device = Device::default();
+        device
+            .load("#ip 5\nseti 7 0 2\nseti 8 0 2\n".lines())
+            .expect("not load");
+
+        let mut hook = Hook::all(vec![Hook::from(Prime(2)), Hook::line(0)]);
+
+        device.step().expect("not step");
+        assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+        assert_eq!(hook.display(&device).to_string(), "all(prime(c), line(0))");
+        assert_eq!(hook.inspect().to_string(), "all(no state, line())");
+
+        device.step().expect("not step");
+        assert_eq!(hook.test(&device).ok(), Some(Action::None));
+    }
+
+    #[test]
+    pub fn test_control_flow_hooks() {
+        use crate::op_code::SETI;
+
+        let mut device = Device::default();
+        device
+            .load("#ip 5\nseti 5 0 2\nseti 5 0 2\nseti 3 0 5\naddi 2 1 2\nseti 6 0 2\n".lines())
+            .expect("not load");
+
+        let mut hooks = [
+            Hook::op_code(SETI),
+            Hook::jump(),
+            Hook::changed(2),
+            Hook::steps(4),
+            Hook::line_range(1..3),
+        ];
+
+        let mut fired = Vec::new();
+
+        while !device.halted {
+            device.step().expect("not step");
+
+            let step = hooks
+                .iter_mut()
+                .map(|h| h.test(&device).ok() == Some(Action::Pause))
+                .collect::<Vec<_>>();
+
+            fired.push(step);
         }
+
+        let t = true;
+        let f = false;
+
+        assert_eq!(
+            fired,
+            vec![
+                vec![t, f, f, f, f],
+                vec![t, f, f, f, t],
+                vec![t, t, f, f, t],
+                vec![t, f, t, t, f],
+                vec![f, f, f, f, f],
+            ]
+        );
+
+        assert_eq!(
+            hooks[2].reason(&device).to_string(),
+            "changed(c) from 5 to 6"
+        );
+    }
+
+    #[test]
+    pub fn test_unique_reset() {
+        let mut device = Device::default();
+        device.load("seti 5 0 2\n".lines()).expect("not load");
+        device.step().expect("not step");
+
+        let mut hook = Hook::unique(2);
+        assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+        assert_eq!(
+            hook.inspect().to_string(),
+            "unique(seen: 1, last: Some(Small(5)))"
+        );
+
+        hook.reset();
+        assert_eq!(hook.inspect().to_string(), "unique(seen: 0, last: None)");
+        assert_eq!(hook.reason(&device).to_string(), "unique(c)");
+
+        assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+        assert_eq!(hook.test(&device).ok(), Some(Action::None));
+    }
+
+    #[test]
+    pub fn test_halting_step_runs_no_line() {
+        use crate::op_code::SETI;
+
+        let mut device = Device::default();
+        device.load("seti 5 0 2\n".lines()).expect("not load");
+
+        let mut hooks = [Hook::op_code(SETI), Hook::line(0), Hook::line_range(0..1)];
+
+        device.step().expect("not step");
+        assert!(!device.halted);
+
+        for h in &mut hooks {
+            assert_eq!(h.test(&device).ok(), Some(Action::Pause));
+        }
+
+        // NB: running off the end doesn't run the last line again.
+        device.step().expect("not step");
+        assert!(device.halted);
+
+        for h in &mut hooks {
+            assert_eq!(h.test(&device).ok(), Some(Action::None));
+        }
+    }
+
+    #[test]
+    pub fn test_halt_and_fault() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\nseti 5 0 2\n".lines())
+            .expect("not load");
+
+        let mut halt = Hook::halt();
+        let mut fault = Hook::fault();
+
+        device.step().expect("not step");
+        assert_eq!(halt.test(&device).ok(), Some(Action::None));
+
+        device.step().expect("not step");
+        assert!(device.halted);
+        assert_eq!(halt.test(&device).ok(), Some(Action::Pause));
+        assert_eq!(fault.test(&device).ok(), Some(Action::None));
+        assert_eq!(halt.reason(&device).to_string(), "halt() program finished");
+        // only fires once.
+        assert_eq!(halt.test(&device).ok(), Some(Action::None));
+
+        device
+            .load("#ip 5\n#reg c=9223372036854775807\naddi 2 1 2\n".lines())
+            .expect("not load");
+        halt.reset();
+
+        device.step().expect("not step");
+        assert_eq!(halt.test(&device).ok(), Some(Action::Pause));
+        assert_eq!(fault.test(&device).ok(), Some(Action::Pause));
+        assert!(fault
+            .reason(&device)
+            .to_string()
+            .starts_with("fault() fault on line 0: "));
     }
 }
@@ -1,4 +1,10 @@
-use crate::{op_code::OpCode, registers::Registers};
+use crate::{
+    format::Format,
+    op_code::{InstructionSet, Notation, OpCode, OpCodeMap, Operand, JMP},
+    registers::Registers,
+    Reg,
+};
+use failure::Error;
 use std::fmt;
 
 /// An instruction.
@@ -7,18 +13,34 @@ pub struct Instruction {
     pub op_code: OpCode,
     pub inputs: [i64; 2],
     pub output: i64,
+    /// Comment associated with the instruction.
+    pub comment: Option<String>,
 }
 
 impl Instruction {
-    pub fn decode(state: &str) -> Option<Instruction> {
+    pub fn decode(set: &InstructionSet, state: &str) -> Option<Instruction> {
         let mut it = state.split(" ");
-        let op_code = OpCode::decode(it.next()?)?;
+        let op_code = set.decode(it.next()?)?;
         let mut it = it.flat_map(|d| str::parse(d).ok());
 
         Some(Instruction {
             op_code,
             inputs: [it.next()?, it.next()?],
             output: it.next()?,
+            comment: None,
+        })
+    }
+
+    /// Decode an instruction where the op code is numeric, like `9 2 1 2`.
+    pub fn decode_numeric(map: &OpCodeMap, state: &str) -> Option<Instruction> {
+        let mut it = state.split_whitespace().map(|d| str::parse(d).ok());
+        let op_code = map.get(it.next()??)?;
+
+        Some(Instruction {
+            op_code,
+            inputs: [it.next()??, it.next()??],
+            output: it.next()??,
+            comment: None,
         })
     }
 
@@ -32,8 +54,111 @@ impl Instruction {
         HumanDisplay {
             inst: self,
             registers,
+            values: false,
+            line: None,
+            previous: None,
         }
     }
+
+    /// Test if this instruction writes to the instruction pointer, and if so
+    /// how.
+    ///
+    /// `line` is the line of the instruction in the program, and `previous`
+    /// the instruction before it. Without a line, jumps relative to the
+    /// instruction pointer are considered computed.
+    pub fn jump(
+        &self,
+        registers: &Registers,
+        line: Option<usize>,
+        previous: Option<&Instruction>,
+    ) -> Option<Jump> {
+        let ip = registers.ip;
+
+        let writes_ip = self.op_code == JMP
+            || (self.op_code.output() == Operand::Register
+                && self.output >= 0
+                && ip == Some(self.output as usize));
+
+        if !writes_ip {
+            return None;
+        }
+
+        let regs = self
+            .op_code
+            .operands()
+            .iter()
+            .zip(self.inputs.iter())
+            .filter(|(operand, _)| **operand == Operand::Register)
+            .map(|(_, reg)| *reg)
+            .collect::<Vec<_>>();
+
+        let only_ip = regs
+            .iter()
+            .all(|reg| *reg >= 0 && Some(*reg as usize) == ip);
+
+        if only_ip && (regs.is_empty() || line.is_some()) {
+            let mut registers = registers.clone();
+
+            if let (Some(ip), Some(line)) = (ip, line) {
+                if registers.set(ip, Reg::from(line as i64)).is_err() {
+                    return Some(Jump::Computed);
+                }
+            }
+
+            return match self.simulate(&registers).and_then(|r| r.ip()) {
+                Ok(line) => Some(Jump::Goto(line)),
+                Err(..) => Some(Jump::Computed),
+            };
+        }
+
+        // NB: a comparison followed by adding its result to the instruction
+        // pointer skips the next instruction if the comparison holds.
+        if let (Notation::Infix("+"), [a, b], Some(previous)) =
+            (self.op_code.notation(), regs.as_slice(), previous)
+        {
+            let flag = if Some(*a as usize) == ip { *b } else { *a };
+
+            let notation = previous.op_code.notation();
+            let is_comparison =
+                notation == Notation::Infix(">") || notation == Notation::Infix("==");
+
+            if (*a != *b)
+                && (Some(*a as usize) == ip || Some(*b as usize) == ip)
+                && is_comparison
+                && previous.op_code.output() == Operand::Register
+                && previous.output == flag
+            {
+                return Some(Jump::Skip);
+            }
+        }
+
+        Some(Jump::Computed)
+    }
+
+    /// Run this instruction against a copy of the registers, and return the
+    /// registers as they would be after it ran.
+    ///
+    /// The instruction pointer is advanced, so the returned registers point to
+    /// the instruction which would run after this one.
+    pub fn simulate(&self, registers: &Registers) -> Result<Registers, Error> {
+        let mut registers = registers.clone();
+        self.op_code
+            .apply(&mut registers, &self.inputs, self.output)?;
+        registers.advance_ip()?;
+        Ok(registers)
+    }
+}
+
+/// How an instruction changes which instruction runs next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    /// Always continue at the given line.
+    Goto(usize),
+    /// Skip the next instruction if the comparison made by the previous
+    /// instruction holds.
+    Skip,
+    /// Continue at a line which is only known when the instruction runs.
+    Computed,
 }
 
 pub struct Display<'a> {
@@ -52,32 +177,237 @@ impl<'a> fmt::Display for Display<'a> {
 pub struct HumanDisplay<'a> {
     inst: &'a Instruction,
     registers: &'a Registers,
+    values: bool,
+    line: Option<usize>,
+    previous: Option<&'a Instruction>,
+}
+
+impl<'a> HumanDisplay<'a> {
+    /// Annotate registers with their current values, and show the value the
+    /// instruction would produce, like `c  = a(12) + b(30) → 42`.
+    pub fn with_values(self) -> Self {
+        HumanDisplay {
+            values: true,
+            ..self
+        }
+    }
+
+    /// Display the instruction as the given line of a program, following the
+    /// `previous` instruction.
+    ///
+    /// This permits jumps relative to the instruction pointer to be shown as
+    /// `goto <line>`, and conditional jumps as `if <cond> skip next`.
+    pub fn at(self, line: usize, previous: Option<&'a Instruction>) -> Self {
+        HumanDisplay {
+            line: Some(line),
+            previous,
+            ..self
+        }
+    }
 }
 
 impl<'a> fmt::Display for HumanDisplay<'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use self::OpCode::*;
-
         let op = self.inst.op_code;
-        let [a, b] = self.inst.inputs;
         let o = self.inst.output;
         let r = self.registers;
 
-        match op {
-            Addr | Mulr | Banr | Borr | Gtrr | Eqrr => write!(
-                fmt,
-                "{:<2} = {} {} {}",
-                r.name(o),
-                r.name(a),
-                op.infix(),
-                r.name(b)
-            ),
-            Addi | Muli | Bani | Bori | Gtri | Eqri => {
-                write!(fmt, "{:<2} = {} {} {}", r.name(o), r.name(a), op.infix(), b)
+        let jump = self.inst.jump(r, self.line, self.previous);
+
+        match jump {
+            Some(Jump::Goto(line)) => write!(fmt, "goto {}", line)?,
+            Some(Jump::Skip) => match self.previous {
+                Some(previous) => write!(fmt, "if {} skip next", Rhs(previous, r, false))?,
+                None => write!(fmt, "goto ({} + 1)", Rhs(self.inst, r, self.values))?,
+            },
+            Some(Jump::Computed) => {
+                write!(fmt, "goto ({} + 1)", Rhs(self.inst, r, self.values))?;
+            }
+            None => {
+                if let Operand::Register = op.output() {
+                    write!(fmt, "{:<2} = ", r.name(o))?;
+                }
+
+                Rhs(self.inst, r, self.values).fmt(fmt)?;
             }
-            Gtir | Eqir => write!(fmt, "{:<2} = {} {} {}", r.name(o), a, op.infix(), r.name(b)),
-            Setr => write!(fmt, "{:<2} = {}", r.name(o), r.name(a)),
-            Seti => write!(fmt, "{:<2} = {}", r.name(o), a),
         }
+
+        if !self.values {
+            return Ok(());
+        }
+
+        let after = self.inst.simulate(r);
+
+        match (jump, after) {
+            (Some(Jump::Goto(..)), _) => (),
+            (Some(..), Ok(after)) => match after.ip() {
+                Ok(ip) => write!(fmt, " → {}", ip)?,
+                Err(e) => write!(fmt, " → error: {}", e)?,
+            },
+            (None, Ok(ref after)) if op.output() == Operand::Register => match after.peek(o) {
+                Ok(value) => write!(fmt, " → {}", r.format(o).display(value))?,
+                Err(e) => write!(fmt, " → error: {}", e)?,
+            },
+            (_, Err(e)) => write!(fmt, " → error: {}", e)?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Display the inputs of an instruction combined according to its notation,
+/// like `a + b`.
+struct Rhs<'a>(&'a Instruction, &'a Registers, bool);
+
+impl fmt::Display for Rhs<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Rhs(inst, r, values) = *self;
+        let op = inst.op_code;
+
+        // NB: immediates are shown in the format of the register they're
+        // combined into.
+        let format = match op.output() {
+            Operand::Register => r.format(inst.output),
+            _ => r.default_format(),
+        };
+
+        let [a, b] = {
+            let [a, b] = op.operands();
+            let [ia, ib] = inst.inputs;
+
+            [
+                Input(r, a, ia, format, values),
+                Input(r, b, ib, format, values),
+            ]
+        };
+
+        match op.notation() {
+            Notation::Infix(infix) => write!(fmt, "{} {} {}", a, infix, b),
+            Notation::Assign => write!(fmt, "{}", a),
+            Notation::Call => {
+                write!(fmt, "{}(", op)?;
+
+                let inputs = [a, b];
+                let mut it = inputs.iter().filter(|i| i.1 != Operand::Ignored).peekable();
+
+                while let Some(input) = it.next() {
+                    input.fmt(fmt)?;
+
+                    if it.peek().is_some() {
+                        write!(fmt, ", ")?;
+                    }
+                }
+
+                write!(fmt, ")")
+            }
+        }
+    }
+}
+
+/// Display a single input to an instruction, with the format to show
+/// immediates in, and whether to show the values of registers.
+struct Input<'a>(&'a Registers, Operand, i64, Format, bool);
+
+impl fmt::Display for Input<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Input(r, operand, value, format, values) = *self;
+
+        match operand {
+            Operand::Register if values => match r.peek(value) {
+                Ok(v) => write!(fmt, "{}({})", r.name(value), r.format(value).display(v)),
+                Err(..) => r.name(value).fmt(fmt),
+            },
+            Operand::Register => r.name(value).fmt(fmt),
+            Operand::Immediate => format.display(&Reg::from(value)).fmt(fmt),
+            Operand::Ignored => value.fmt(fmt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Instruction;
+    use crate::{format::Format, op_code::InstructionSet, registers::Registers, Reg};
+
+    #[test]
+    pub fn test_human_display_with_values() {
+        let mut registers = Registers::default();
+        let set = InstructionSet::default();
+
+        registers.set(0, Reg::from(12)).expect("not set");
+        registers.set(1, Reg::from(30)).expect("not set");
+
+        let inst = Instruction::decode(&set, "addr 0 1 2").expect("not decode");
+        assert_eq!(
+            inst.human_display(&registers).with_values().to_string(),
+            "c  = a(12) + b(30) → 42"
+        );
+
+        registers.set_format(2, Format::Hex);
+        let inst = Instruction::decode(&set, "muli 2 16 2").expect("not decode");
+        assert_eq!(
+            inst.human_display(&registers).with_values().to_string(),
+            "c  = c(0x0) * 0x10 → 0x0"
+        );
+
+        // NB: registers are left untouched.
+        assert!(!registers.is_read(0));
+        assert!(!registers.is_written(2));
+    }
+
+    #[test]
+    pub fn test_human_display_jumps() {
+        let mut registers = Registers::default();
+        registers.ip = Some(5);
+        let set = InstructionSet::default();
+
+        let decode = |s| Instruction::decode(&set, s).expect("not decode");
+        let compare = decode("eqrr 2 3 2");
+
+        let inst = decode("seti 16 0 5");
+        assert_eq!(inst.human_display(&registers).to_string(), "goto 17");
+
+        let inst = decode("addi 5 1 5");
+        assert_eq!(
+            inst.human_display(&registers).at(7, None).to_string(),
+            "goto 9"
+        );
+
+        let inst = decode("addr 2 5 5");
+        assert_eq!(
+            inst.human_display(&registers)
+                .at(8, Some(&compare))
+                .to_string(),
+            "if c == d skip next"
+        );
+
+        let inst = decode("addr 5 4 5");
+        assert_eq!(
+            inst.human_display(&registers).at(8, None).to_string(),
+            "goto (ip + e + 1)"
+        );
+
+        registers.set(4, Reg::from(3)).expect("not set");
+        registers.set(5, Reg::from(8)).expect("not set");
+        assert_eq!(
+            inst.human_display(&registers)
+                .at(8, None)
+                .with_values()
+                .to_string(),
+            "goto (ip(8) + e(3) + 1) → 12"
+        );
+    }
+
+    #[test]
+    pub fn test_simulate() {
+        let mut registers = Registers::default();
+        registers.ip = Some(5);
+        let set = InstructionSet::default();
+
+        let inst = Instruction::decode(&set, "addi 5 3 5").expect("not decode");
+        let after = inst.simulate(&registers).expect("not simulate");
+        assert_eq!(after.ip().ok(), Some(4));
+        assert_eq!(registers.ip().ok(), Some(0));
     }
 }
@@ -1,14 +1,25 @@
+pub mod analysis;
+pub mod arithmetic;
+pub mod assembler;
+pub mod bits;
+pub mod breakpoint;
+pub mod compiler;
 pub mod device;
 mod events;
+pub mod expr;
+pub mod flow;
+pub mod format;
+pub mod history;
 pub mod hook;
 pub mod instruction;
 pub mod op_code;
 mod parser;
-mod registers;
+mod reg;
+pub mod registers;
 pub mod tui;
 pub mod visuals;
 
-pub type Reg = i64;
+pub use self::reg::Reg;
 
 /// Convert into a registers.
 pub trait AsReg {
@@ -1,12 +1,25 @@
-use elfdb::{device::Device, tui, visuals::Visuals};
-use failure::{format_err, Error, ResultExt};
+use elfdb::{arithmetic::Arithmetic, device::Device, op_code::OpCodeMap, tui, visuals::Visuals};
+use failure::{bail, format_err, Error, ResultExt};
 use std::path::Path;
 
-fn run<'a, V>(mut visuals: V, initial: Option<&Path>) -> Result<(), Error>
+fn run<'a, V>(
+    mut visuals: V,
+    mode: Arithmetic,
+    op_codes: Option<&Path>,
+    initial: Option<&Path>,
+) -> Result<(), Error>
 where
     V: Visuals,
 {
     let mut device = Device::default();
+    device.registers.set_arithmetic(mode)?;
+
+    if let Some(op_codes) = op_codes {
+        device.op_code_map =
+            OpCodeMap::load_path(&device.instruction_set, op_codes).with_context(|_| {
+                format_err!("failed to load op codes from path `{}`", op_codes.display())
+            })?;
+    }
 
     if let Some(initial) = initial {
         device.load_path(initial).with_context(|_| {
@@ -40,13 +53,43 @@ fn main() -> Result<(), Error> {
     let mut args = env::args();
     args.next();
 
-    let program = match args.next() {
-        None => None,
-        Some(program) => Some(PathBuf::from(program)),
-    };
+    let mut mode = Arithmetic::default();
+    let mut op_codes = None;
+    let mut program = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mode" | "-m" => {
+                let name = match args.next() {
+                    Some(name) => name,
+                    None => bail!("expected argument to `{}`", arg),
+                };
+
+                mode = match Arithmetic::by_name(&name) {
+                    Some(mode) => mode,
+                    None => bail!(
+                        "no such mode `{}`, expected one of: {}",
+                        name,
+                        Arithmetic::names().join(", ")
+                    ),
+                };
+            }
+            "--opcodes" => {
+                op_codes = match args.next() {
+                    Some(path) => Some(PathBuf::from(path)),
+                    None => bail!("expected argument to `{}`", arg),
+                };
+            }
+            _ => {
+                program = Some(PathBuf::from(arg));
+            }
+        }
+    }
 
     run(
         tui::Terminal::new().interactive(),
+        mode,
+        op_codes.as_ref().map(|p| p.as_path()),
         program.as_ref().map(|p| p.as_path()),
     )?;
     Ok(())
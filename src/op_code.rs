@@ -1,166 +1,608 @@
-use crate::registers::Registers;
-use failure::Error;
-use std::fmt;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub enum OpCode {
-    Addr,
-    Addi,
-    Mulr,
-    Muli,
-    Banr,
-    Bani,
-    Borr,
-    Bori,
-    Setr,
-    Seti,
-    Gtir,
-    Gtri,
-    Gtrr,
-    Eqir,
-    Eqri,
-    Eqrr,
+use crate::{
+    arithmetic::{Arithmetic, Op},
+    registers::Registers,
+    Reg,
+};
+use failure::{bail, format_err, Error};
+use std::{cmp, collections::BTreeMap, fmt, hash, path};
+
+/// The kind of an input to an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// The input refers to a register.
+    Register,
+    /// The input is an immediate value.
+    Immediate,
+    /// The input is not used.
+    Ignored,
+}
+
+/// How an operation is displayed when using human decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// As an infix operator, like `c = a + b`.
+    Infix(&'static str),
+    /// As an assignment of the first input, like `c = a`.
+    Assign,
+    /// As a function call using the name of the operation, like `c = divr(a, b)`.
+    Call,
+}
+
+/// An operation which can be registered in an [InstructionSet].
+pub trait Operation: Sync {
+    /// The name of the operation, like `addr`.
+    fn name(&self) -> &'static str;
+
+    /// The kind of each input to the operation.
+    fn operands(&self) -> [Operand; 2];
+
+    /// The kind of the output of the operation.
+    fn output(&self) -> Operand {
+        Operand::Register
+    }
+
+    /// How the operation is displayed when using human decoding.
+    fn notation(&self) -> Notation {
+        Notation::Call
+    }
+
+    /// If this is one of the built-in operations, and not a custom operation
+    /// which might share its name.
+    #[doc(hidden)]
+    fn is_builtin(&self) -> bool {
+        false
+    }
+
+    /// Evaluate the operation for the given input values, using the given
+    /// arithmetic semantics.
+    fn eval(&self, arithmetic: Arithmetic, a: Reg, b: Reg) -> Result<Reg, Error>;
+
+    /// Apply the operation to the registers.
+    ///
+    /// By default, this reads the inputs as described by `operands`, and stores
+    /// the result of `eval` in the output register.
+    fn apply(&self, r: &mut Registers, inputs: &[i64; 2], o: i64) -> Result<(), Error> {
+        let [a, b] = self.operands();
+        let a = input(r, a, inputs[0])?;
+        let b = input(r, b, inputs[1])?;
+        let value = self.eval(r.arithmetic(), a, b)?;
+
+        if let Operand::Register = self.output() {
+            r.write(o, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the value of an input.
+pub fn input(r: &mut Registers, operand: Operand, value: i64) -> Result<Reg, Error> {
+    match operand {
+        Operand::Register => r.reg(value),
+        Operand::Immediate => r.arithmetic().normalize(Reg::from(value)),
+        Operand::Ignored => Ok(Reg::default()),
+    }
+}
+
+/// A reference to an operation.
+#[derive(Clone, Copy)]
+pub struct OpCode(&'static dyn Operation);
+
+impl OpCode {
+    /// Construct an op code from the given operation.
+    pub const fn new(operation: &'static dyn Operation) -> OpCode {
+        OpCode(operation)
+    }
+
+    /// The name of the op code, like `addr`.
+    pub fn name(self) -> &'static str {
+        self.0.name()
+    }
+
+    /// Get the kind of each input for this op code.
+    pub fn operands(self) -> [Operand; 2] {
+        self.0.operands()
+    }
+
+    /// Get the kind of the output for this op code.
+    pub fn output(self) -> Operand {
+        self.0.output()
+    }
+
+    /// How the op code is displayed when using human decoding.
+    pub fn notation(self) -> Notation {
+        self.0.notation()
+    }
+
+    /// Test if the op code is one of the built-in operations, in either the
+    /// original or the extended instruction set.
+    ///
+    /// Custom operations registered under the name of a built-in one are not
+    /// built-in.
+    pub fn is_builtin(self) -> bool {
+        self.0.is_builtin()
+    }
+
+    /// Apply the given operation to the registers.
+    pub fn apply(self, r: &mut Registers, inputs: &[i64; 2], o: i64) -> Result<(), Error> {
+        self.0.apply(r, inputs, o)
+    }
+}
+
+impl fmt::Debug for OpCode {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "OpCode({})", self.name())
+    }
 }
 
 impl fmt::Display for OpCode {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use self::OpCode::*;
-
-        let name = match *self {
-            Addr => "addr",
-            Addi => "addi",
-            Mulr => "mulr",
-            Muli => "muli",
-            Banr => "banr",
-            Bani => "bani",
-            Borr => "borr",
-            Bori => "bori",
-            Setr => "setr",
-            Seti => "seti",
-            Gtir => "gtir",
-            Gtri => "gtri",
-            Gtrr => "gtrr",
-            Eqir => "eqir",
-            Eqri => "eqri",
-            Eqrr => "eqrr",
-        };
+        self.name().fmt(fmt)
+    }
+}
 
-        name.fmt(fmt)
+impl PartialEq for OpCode {
+    fn eq(&self, other: &OpCode) -> bool {
+        self.name() == other.name()
     }
 }
 
-impl OpCode {
-    pub fn decode(input: &str) -> Option<OpCode> {
-        use self::OpCode::*;
-
-        let out = match input {
-            "addr" => Addr,
-            "addi" => Addi,
-            "mulr" => Mulr,
-            "muli" => Muli,
-            "banr" => Banr,
-            "bani" => Bani,
-            "borr" => Borr,
-            "bori" => Bori,
-            "setr" => Setr,
-            "seti" => Seti,
-            "gtir" => Gtir,
-            "gtri" => Gtri,
-            "gtrr" => Gtrr,
-            "eqir" => Eqir,
-            "eqri" => Eqri,
-            "eqrr" => Eqrr,
-            _ => return None,
-        };
+impl Eq for OpCode {}
 
-        Some(out)
+impl PartialOrd for OpCode {
+    fn partial_cmp(&self, other: &OpCode) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
     }
+}
+
+impl Ord for OpCode {
+    fn cmp(&self, other: &OpCode) -> cmp::Ordering {
+        self.name().cmp(other.name())
+    }
+}
+
+impl hash::Hash for OpCode {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.name().hash(state);
+    }
+}
 
-    /// Get the infix version of this op code.
-    pub fn infix(self) -> &'static str {
-        use self::OpCode::*;
+/// A set of operations, which instructions are decoded using.
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    op_codes: Vec<OpCode>,
+}
 
-        match self {
-            Addr => "+",
-            Addi => "+",
-            Mulr => "*",
-            Muli => "*",
-            Banr => "&",
-            Bani => "&",
-            Borr => "|",
-            Bori => "|",
-            Setr => "?",
-            Seti => "?",
-            Gtir => ">",
-            Gtri => ">",
-            Gtrr => ">",
-            Eqir => "==",
-            Eqri => "==",
-            Eqrr => "==",
+impl InstructionSet {
+    /// Construct an instruction set without any operations.
+    pub fn empty() -> InstructionSet {
+        InstructionSet {
+            op_codes: Vec::new(),
         }
     }
 
-    /// Apply the given operation to the registers.
-    pub fn apply(&self, r: &mut Registers, inputs: &[i64; 2], o: i64) -> Result<(), Error> {
-        use self::OpCode::*;
-
-        let [a, b] = *inputs;
-
-        *r.reg_mut(o)? = match *self {
-            Addr => r.reg(a)? + r.reg(b)?,
-            Addi => r.reg(a)? + b,
-            Mulr => r.reg(a)? * r.reg(b)?,
-            Muli => r.reg(a)? * b,
-            Banr => r.reg(a)? & r.reg(b)?,
-            Bani => r.reg(a)? & b,
-            Borr => r.reg(a)? | r.reg(b)?,
-            Bori => r.reg(a)? | b,
-            Setr => r.reg(a)?,
-            Seti => a,
-            Gtir => {
-                if a > r.reg(b)? {
-                    1
-                } else {
-                    0
-                }
-            }
-            Gtri => {
-                if r.reg(a)? > b {
-                    1
-                } else {
-                    0
-                }
-            }
-            Gtrr => {
-                if r.reg(a)? > r.reg(b)? {
-                    1
-                } else {
-                    0
-                }
-            }
-            Eqir => {
-                if a == r.reg(b)? {
-                    1
-                } else {
-                    0
-                }
-            }
-            Eqri => {
-                if r.reg(a)? == b {
-                    1
-                } else {
-                    0
-                }
-            }
-            Eqrr => {
-                if r.reg(a)? == r.reg(b)? {
-                    1
-                } else {
-                    0
-                }
+    /// The original instruction set, extended with `subr`, `subi`, `divr`,
+    /// `divi`, `modr`, `modi`, and `jmp`.
+    pub fn extended() -> InstructionSet {
+        let mut set = InstructionSet::default();
+
+        for op_code in EXTENDED.iter() {
+            set.register(*op_code);
+        }
+
+        set
+    }
+
+    /// Look up an instruction set by name.
+    pub fn by_name(name: &str) -> Option<InstructionSet> {
+        match name {
+            "elfcode" => Some(InstructionSet::default()),
+            "extended" => Some(InstructionSet::extended()),
+            _ => None,
+        }
+    }
+
+    /// Register an op code, replacing any existing op code with the same name.
+    pub fn register(&mut self, op_code: OpCode) {
+        match self.op_codes.iter_mut().find(|o| **o == op_code) {
+            Some(existing) => *existing = op_code,
+            None => self.op_codes.push(op_code),
+        }
+    }
+
+    /// Decode an op code by name.
+    pub fn decode(&self, name: &str) -> Option<OpCode> {
+        self.op_codes.iter().find(|o| o.name() == name).cloned()
+    }
+
+    /// Iterate over all op codes in the set.
+    pub fn iter(&self) -> impl Iterator<Item = OpCode> + '_ {
+        self.op_codes.iter().cloned()
+    }
+}
+
+impl Default for InstructionSet {
+    fn default() -> InstructionSet {
+        InstructionSet {
+            op_codes: ELFCODE.to_vec(),
+        }
+    }
+}
+
+/// A mapping from numeric op code ids to op codes.
+///
+/// Used to decode programs where every instruction is written as four
+/// integers, like `9 2 1 2`.
+#[derive(Debug, Clone, Default)]
+pub struct OpCodeMap {
+    op_codes: BTreeMap<i64, OpCode>,
+}
+
+impl OpCodeMap {
+    /// Load a mapping from the specified path, see [`OpCodeMap::parse`].
+    pub fn load_path(
+        set: &InstructionSet,
+        path: impl AsRef<path::Path>,
+    ) -> Result<OpCodeMap, Error> {
+        use std::{fs::File, io::Read};
+
+        let mut f = File::open(path.as_ref())?;
+        let mut input = String::new();
+        f.read_to_string(&mut input)?;
+        OpCodeMap::parse(set, input.lines())
+    }
+
+    /// Parse a mapping where every line is an id and the name of an op code,
+    /// like `9 addr`.
+    ///
+    /// Blank lines and comments starting with `;` or `//` are ignored.
+    pub fn parse<'a>(
+        set: &InstructionSet,
+        input: impl Iterator<Item = &'a str>,
+    ) -> Result<OpCodeMap, Error> {
+        let mut map = OpCodeMap::default();
+
+        for (n, line) in input.enumerate() {
+            let (code, _) = crate::device::split_comment(line);
+            let mut it = code.split_whitespace();
+
+            match (it.next(), it.next()) {
+                (Some(id), Some(name)) => map
+                    .insert_named(set, id, name)
+                    .map_err(|e| format_err!("line {}: {}", n + 1, e))?,
+                (None, _) => continue,
+                _ => bail!("line {}: expected `<id> <name>`", n + 1),
             }
+        }
+
+        Ok(map)
+    }
+
+    /// Map the given id to an op code by name, looking it up in the given
+    /// instruction set.
+    pub fn insert_named(
+        &mut self,
+        set: &InstructionSet,
+        id: &str,
+        name: &str,
+    ) -> Result<(), Error> {
+        let id =
+            str::parse::<i64>(id).map_err(|e| format_err!("bad op code id `{}`: {}", id, e))?;
+
+        let op_code = match set.decode(name) {
+            Some(op_code) => op_code,
+            None => bail!("no such op code: {}", name),
         };
 
+        self.insert(id, op_code);
+        Ok(())
+    }
+
+    /// Map the given id to an op code, returning the op code previously
+    /// mapped to it.
+    pub fn insert(&mut self, id: i64, op_code: OpCode) -> Option<OpCode> {
+        self.op_codes.insert(id, op_code)
+    }
+
+    /// Get the op code mapped to the given id.
+    pub fn get(&self, id: i64) -> Option<OpCode> {
+        self.op_codes.get(&id).cloned()
+    }
+
+    /// Number of mapped op codes.
+    pub fn len(&self) -> usize {
+        self.op_codes.len()
+    }
+
+    /// Test if no op codes are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.op_codes.is_empty()
+    }
+
+    /// Iterate over all mappings, ordered by id.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, OpCode)> + '_ {
+        self.op_codes.iter().map(|(id, op_code)| (*id, *op_code))
+    }
+
+    /// Display the mapping in the format understood by
+    /// [`OpCodeMap::parse`].
+    pub fn display(&self) -> OpCodeMapDisplay<'_> {
+        OpCodeMapDisplay { map: self }
+    }
+}
+
+pub struct OpCodeMapDisplay<'a> {
+    map: &'a OpCodeMap,
+}
+
+impl fmt::Display for OpCodeMapDisplay<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (id, op_code) in self.map.iter() {
+            writeln!(fmt, "{} {}", id, op_code)?;
+        }
+
         Ok(())
     }
 }
+
+/// Kinds of built-in operations.
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Set,
+    Gt,
+    Eq,
+}
+
+/// A built-in operation.
+struct Builtin {
+    name: &'static str,
+    operands: [Operand; 2],
+    kind: Kind,
+}
+
+impl Operation for Builtin {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn is_builtin(&self) -> bool {
+        true
+    }
+
+    fn operands(&self) -> [Operand; 2] {
+        self.operands
+    }
+
+    fn notation(&self) -> Notation {
+        match self.kind {
+            Kind::Add => Notation::Infix("+"),
+            Kind::Sub => Notation::Infix("-"),
+            Kind::Mul => Notation::Infix("*"),
+            Kind::Div => Notation::Infix("/"),
+            Kind::Mod => Notation::Infix("%"),
+            Kind::And => Notation::Infix("&"),
+            Kind::Or => Notation::Infix("|"),
+            Kind::Set => Notation::Assign,
+            Kind::Gt => Notation::Infix(">"),
+            Kind::Eq => Notation::Infix("=="),
+        }
+    }
+
+    fn eval(&self, arithmetic: Arithmetic, a: Reg, b: Reg) -> Result<Reg, Error> {
+        let op = match self.kind {
+            Kind::Add => Op::Add,
+            Kind::Sub => Op::Sub,
+            Kind::Mul => Op::Mul,
+            Kind::Div => Op::Div,
+            Kind::Mod => Op::Rem,
+            Kind::And => Op::And,
+            Kind::Or => Op::Or,
+            Kind::Set => return Ok(a),
+            Kind::Gt => return Ok(Reg::from(a > b)),
+            Kind::Eq => return Ok(Reg::from(a == b)),
+        };
+
+        arithmetic.eval(op, &a, &b)
+    }
+}
+
+/// A dedicated unconditional jump, which sets the instruction pointer to the
+/// given immediate.
+struct Jmp;
+
+impl Operation for Jmp {
+    fn name(&self) -> &'static str {
+        "jmp"
+    }
+
+    fn is_builtin(&self) -> bool {
+        true
+    }
+
+    fn operands(&self) -> [Operand; 2] {
+        [Operand::Immediate, Operand::Ignored]
+    }
+
+    fn output(&self) -> Operand {
+        Operand::Ignored
+    }
+
+    fn eval(&self, arithmetic: Arithmetic, a: Reg, _: Reg) -> Result<Reg, Error> {
+        // NB: the instruction pointer is incremented after the instruction.
+        arithmetic.eval(Op::Sub, &a, &Reg::from(1))
+    }
+
+    fn apply(&self, r: &mut Registers, inputs: &[i64; 2], _: i64) -> Result<(), Error> {
+        let value = self.eval(r.arithmetic(), Reg::from(inputs[0]), Reg::default())?;
+        r.write_ip(value)
+    }
+}
+
+macro_rules! builtin {
+    ($name:expr, $a:ident, $b:ident, $kind:ident) => {
+        OpCode::new(&Builtin {
+            name: $name,
+            operands: [Operand::$a, Operand::$b],
+            kind: Kind::$kind,
+        })
+    };
+}
+
+pub const ADDR: OpCode = builtin!("addr", Register, Register, Add);
+pub const ADDI: OpCode = builtin!("addi", Register, Immediate, Add);
+pub const MULR: OpCode = builtin!("mulr", Register, Register, Mul);
+pub const MULI: OpCode = builtin!("muli", Register, Immediate, Mul);
+pub const BANR: OpCode = builtin!("banr", Register, Register, And);
+pub const BANI: OpCode = builtin!("bani", Register, Immediate, And);
+pub const BORR: OpCode = builtin!("borr", Register, Register, Or);
+pub const BORI: OpCode = builtin!("bori", Register, Immediate, Or);
+pub const SETR: OpCode = builtin!("setr", Register, Ignored, Set);
+pub const SETI: OpCode = builtin!("seti", Immediate, Ignored, Set);
+pub const GTIR: OpCode = builtin!("gtir", Immediate, Register, Gt);
+pub const GTRI: OpCode = builtin!("gtri", Register, Immediate, Gt);
+pub const GTRR: OpCode = builtin!("gtrr", Register, Register, Gt);
+pub const EQIR: OpCode = builtin!("eqir", Immediate, Register, Eq);
+pub const EQRI: OpCode = builtin!("eqri", Register, Immediate, Eq);
+pub const EQRR: OpCode = builtin!("eqrr", Register, Register, Eq);
+
+pub const SUBR: OpCode = builtin!("subr", Register, Register, Sub);
+pub const SUBI: OpCode = builtin!("subi", Register, Immediate, Sub);
+pub const DIVR: OpCode = builtin!("divr", Register, Register, Div);
+pub const DIVI: OpCode = builtin!("divi", Register, Immediate, Div);
+pub const MODR: OpCode = builtin!("modr", Register, Register, Mod);
+pub const MODI: OpCode = builtin!("modi", Register, Immediate, Mod);
+pub const JMP: OpCode = OpCode::new(&Jmp);
+
+/// The original ElfCode operations.
+pub const ELFCODE: [OpCode; 16] = [
+    ADDR, ADDI, MULR, MULI, BANR, BANI, BORR, BORI, SETR, SETI, GTIR, GTRI, GTRR, EQIR, EQRI, EQRR,
+];
+
+/// Operations in the extended instruction set.
+pub const EXTENDED: [OpCode; 7] = [SUBR, SUBI, DIVR, DIVI, MODR, MODI, JMP];
+
+#[cfg(test)]
+mod tests {
+    use super::{OpCode, Operand, Operation, ADDR};
+    use crate::{arithmetic::Arithmetic, device::Device, Reg};
+    use failure::Error;
+    use std::cmp;
+
+    struct Maxi;
+
+    impl Operation for Maxi {
+        fn name(&self) -> &'static str {
+            "maxi"
+        }
+
+        fn operands(&self) -> [Operand; 2] {
+            [Operand::Register, Operand::Immediate]
+        }
+
+        fn eval(&self, _: Arithmetic, a: Reg, b: Reg) -> Result<Reg, Error> {
+            Ok(cmp::max(a, b))
+        }
+    }
+
+    /// A custom operation which replaces `addr`.
+    struct Addr;
+
+    impl Operation for Addr {
+        fn name(&self) -> &'static str {
+            "addr"
+        }
+
+        fn operands(&self) -> [Operand; 2] {
+            [Operand::Register, Operand::Register]
+        }
+
+        fn eval(&self, _: Arithmetic, a: Reg, b: Reg) -> Result<Reg, Error> {
+            Ok(cmp::max(a, b))
+        }
+    }
+
+    #[test]
+    pub fn test_custom_operation() {
+        let mut device = Device::default();
+        device.instruction_set.register(OpCode::new(&Maxi));
+
+        let program = "#ip 5\nseti 3 0 0\nmaxi 0 48 1\n";
+        device.load(program.lines()).expect("not load");
+
+        assert_eq!(
+            device.instructions[1]
+                .human_display(&device.registers)
+                .to_string(),
+            "b  = maxi(a, 48)"
+        );
+
+        while !device.halted {
+            device.step().expect("not step");
+        }
+
+        assert_eq!(device.registers.iter().nth(1), Some(Reg::from(48)));
+    }
+
+    #[test]
+    pub fn test_u32_writes_are_normalized() {
+        let mut device = Device::default();
+        device
+            .registers
+            .set_arithmetic(Arithmetic::U32)
+            .expect("not set arithmetic");
+
+        let program = "seti -1 0 0\nseti 5000000000 0 1\ngtir -1 1 2\nmaxi 1 -1 3\n";
+        device.instruction_set.register(OpCode::new(&Maxi));
+        device.load(program.lines()).expect("not load");
+
+        while !device.halted {
+            device.step().expect("not step");
+        }
+
+        assert!(device.fault.is_none());
+        assert_eq!(
+            device.registers.iter().take(4).collect::<Vec<_>>(),
+            vec![
+                Reg::from(0xffff_ffff),
+                Reg::from(5_000_000_000 & 0xffff_ffff),
+                Reg::from(1),
+                Reg::from(0xffff_ffff),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_isa_is_per_program() {
+        let mut device = Device::default();
+        device.instruction_set.register(OpCode::new(&Maxi));
+
+        let program = "#isa extended\nsubi 0 1 0\nmaxi 0 48 1\n";
+        device.load(program.lines()).expect("not load");
+        assert_eq!(device.instructions.len(), 2);
+
+        // NB: the next program goes back to the original instruction set.
+        assert!(device.load("subi 0 1 0\n".lines()).is_err());
+        device.load("maxi 0 48 1\n".lines()).expect("not load");
+
+        // custom operations which replace a built-in one are kept.
+        assert!(ADDR.is_builtin());
+        assert!(!OpCode::new(&Addr).is_builtin());
+        device.instruction_set.register(OpCode::new(&Addr));
+
+        let program = "#isa extended\nseti 3 0 0\nseti 5 0 1\naddr 0 1 2\n";
+        device.load(program.lines()).expect("not load");
+
+        while !device.halted {
+            device.step().expect("not step");
+        }
+
+        assert_eq!(device.registers.iter().nth(2), Some(Reg::from(5)));
+    }
+}
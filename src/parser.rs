@@ -1,229 +1,680 @@
+//! Parser for breakpoint conditions.
+//!
+//! Conditions are written as infix expressions, like
+//! `line(28) && (a > b || c % 3 == 0)`, where the prefix hooks like
+//! `line(28)` or `unique(c)` can be used as conditions as well.
+
 use crate::{
+    arithmetic,
     device::Device,
+    expr::Expr,
+    format::Format,
     hook::{Hook, Op},
+    op_code::OpCode,
+    Reg,
 };
-use failure::{bail, Error};
+use failure::Error;
+use num_bigint::BigInt;
+use std::{error, fmt};
 
+/// An error raised while parsing, pointing at the column where parsing failed.
 #[derive(Debug)]
-pub enum Token {
-    String(String),
-    Immediate(i64),
+pub struct ParseError {
+    /// The column where parsing failed, starting at 1.
+    pub column: usize,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{} at column {}", self.message, self.column)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Bail with a parse error at the given column.
+macro_rules! bail_at {
+    ($column:expr, $($arg:tt)*) => {
+        return Err(ParseError {
+            column: $column,
+            message: format!($($arg)*),
+        })
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(Reg),
     Open,
     Close,
     Comma,
+    DotDot,
+    Bang,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Amp,
+    Pipe,
+    AmpAmp,
+    PipePipe,
+    EqEq,
+    BangEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
 }
 
-impl Token {
-    fn as_string(self) -> Result<String, Error> {
-        match self {
-            Token::String(string) => Ok(string),
-            other => bail!("expected string, but got: {:?}", other),
-        }
-    }
+impl fmt::Display for Token {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match *self {
+            Token::Ident(ref ident) => return write!(fmt, "`{}`", ident),
+            Token::Number(ref number) => return write!(fmt, "`{}`", number),
+            Token::Open => "(",
+            Token::Close => ")",
+            Token::Comma => ",",
+            Token::DotDot => "..",
+            Token::Bang => "!",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Amp => "&",
+            Token::Pipe => "|",
+            Token::AmpAmp => "&&",
+            Token::PipePipe => "||",
+            Token::EqEq => "==",
+            Token::BangEq => "!=",
+            Token::Lt => "<",
+            Token::LtEq => "<=",
+            Token::Gt => ">",
+            Token::GtEq => ">=",
+        };
 
-    fn as_immediate(self) -> Result<i64, Error> {
-        match self {
-            Token::Immediate(immediate) => Ok(immediate),
-            other => bail!("expected immediate, but got: {:?}", other),
-        }
+        write!(fmt, "`{}`", s)
     }
+}
+
+/// Split the input into tokens and the columns they start at.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while let Some(&c) = chars.get(i) {
+        let column = i + 1;
+        let next = chars.get(i + 1).cloned();
+
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('a'..='z', _) | ('_', _) => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || **c == '_')
+                    .count();
 
-    fn as_register(self, device: &Device) -> Result<usize, Error> {
-        let out = match self.as_string()?.as_str() {
-            "a" => 0,
-            "b" => 1,
-            "c" => 2,
-            "d" => 3,
-            "e" => 4,
-            "f" => 5,
-            "ip" => device.registers.ip,
-            other => bail!("not a register: {}", other),
+                let ident = chars[i..i + len].iter().collect();
+                (Token::Ident(ident), len)
+            }
+            ('0'..='9', _) => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .count();
+
+                let literal = chars[i..i + len].iter().collect::<String>();
+                (Token::Number(number(column, &literal)?), len)
+            }
+            ('&', Some('&')) => (Token::AmpAmp, 2),
+            ('|', Some('|')) => (Token::PipePipe, 2),
+            ('=', Some('=')) => (Token::EqEq, 2),
+            ('!', Some('=')) => (Token::BangEq, 2),
+            ('<', Some('=')) => (Token::LtEq, 2),
+            ('>', Some('=')) => (Token::GtEq, 2),
+            ('.', Some('.')) => (Token::DotDot, 2),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            (',', _) => (Token::Comma, 1),
+            ('!', _) => (Token::Bang, 1),
+            ('+', _) => (Token::Plus, 1),
+            ('-', _) => (Token::Minus, 1),
+            ('*', _) => (Token::Star, 1),
+            ('/', _) => (Token::Slash, 1),
+            ('%', _) => (Token::Percent, 1),
+            ('&', _) => (Token::Amp, 1),
+            ('|', _) => (Token::Pipe, 1),
+            ('<', _) => (Token::Lt, 1),
+            ('>', _) => (Token::Gt, 1),
+            ('=', _) => bail_at!(column, "unexpected `=`, did you mean `==`?"),
+            (c, _) => bail_at!(column, "unexpected character `{}`", c),
         };
 
-        Ok(out)
+        tokens.push((column, token));
+        i += len;
     }
 
-    fn as_open(self) -> Result<(), Error> {
-        match self {
-            Token::Open => Ok(()),
-            _ => bail!("expected string"),
-        }
-    }
+    return Ok(tokens);
 
-    fn as_close(self) -> Result<(), Error> {
-        match self {
-            Token::Close => Ok(()),
-            _ => bail!("expected string"),
-        }
-    }
+    /// Parse a number literal, like `42`, `0x2a`, or `0b101010`.
+    fn number(column: usize, literal: &str) -> Result<Reg, ParseError> {
+        let (digits, radix) = if let Some(digits) = literal.strip_prefix("0x") {
+            (digits, 16)
+        } else if let Some(digits) = literal.strip_prefix("0b") {
+            (digits, 2)
+        } else {
+            (literal, 10)
+        };
 
-    fn as_comma(self) -> Result<(), Error> {
-        match self {
-            Token::Comma => Ok(()),
-            _ => bail!("expected string"),
+        match BigInt::parse_bytes(digits.as_bytes(), radix) {
+            Some(value) if !digits.is_empty() => Ok(Reg::big(value)),
+            _ => bail_at!(column, "bad number `{}`", literal),
         }
     }
 }
 
-fn tokenize<'a>(input: &'a str) -> Tokenizer<'a> {
-    Tokenizer {
-        it: input.chars().peekable(),
-    }
+/// A parsed, but not yet resolved, condition.
+#[derive(Debug)]
+enum Node {
+    Number(Reg),
+    Ident(String),
+    Call(String, Vec<(usize, Node)>),
+    Range(Box<(usize, Node)>, Box<(usize, Node)>),
+    Unary(Token, Box<(usize, Node)>),
+    Binary(Token, Box<(usize, Node)>, Box<(usize, Node)>),
 }
 
-pub struct Tokenizer<'a> {
-    it: std::iter::Peekable<std::str::Chars<'a>>,
+/// Get the precedence of a binary operator, higher binds tighter.
+fn precedence(token: &Token) -> Option<u32> {
+    let precedence = match *token {
+        Token::PipePipe => 1,
+        Token::AmpAmp => 2,
+        Token::EqEq | Token::BangEq | Token::Lt | Token::LtEq | Token::Gt | Token::GtEq => 3,
+        Token::Pipe => 4,
+        Token::Amp => 5,
+        Token::Plus | Token::Minus => 6,
+        Token::Star | Token::Slash | Token::Percent => 7,
+        _ => return None,
+    };
+
+    Some(precedence)
 }
 
-impl<'a> Tokenizer<'a> {
-    pub fn try_next(&mut self) -> Result<Option<Token>, Error> {
-        loop {
-            let t = match self.it.peek().cloned() {
-                Some(c) => match c {
-                    'a'..='z' => return Ok(Some(Token::String(self.string()))),
-                    '0'..='9' => return Ok(Some(Token::Immediate(self.immediate()?))),
-                    '(' => Token::Open,
-                    ')' => Token::Close,
-                    ',' => Token::Comma,
-                    ' ' => {
-                        self.it.next();
-                        continue;
-                    }
-                    c => bail!("unexpected character: {}", c),
-                },
-                None => return Ok(None),
-            };
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Column after the last character of input.
+    end: usize,
+    device: &'a Device,
+}
 
-            self.it.next();
-            return Ok(Some(t));
-        }
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    /// The column of the next token.
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(c, _)| *c)
+            .unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let next = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        next
     }
 
-    pub fn next(&mut self) -> Result<Token, Error> {
-        match self.try_next()? {
-            Some(next) => Ok(next),
-            None => bail!("expected token"),
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        match self.next() {
+            Some((_, ref token)) if *token == expected => Ok(()),
+            Some((column, token)) => bail_at!(column, "expected {}, but got {}", expected, token),
+            None => bail_at!(self.end, "expected {}, but got end of input", expected),
         }
     }
 
-    pub fn as_hook(&mut self, device: &Device) -> Result<Hook, Error> {
-        let name = self.next()?.as_string()?;
+    /// Parse an expression with operators that bind at least as tightly as
+    /// `min`.
+    fn expr(&mut self, min: u32) -> Result<(usize, Node), ParseError> {
+        let mut lhs = self.unary()?;
 
-        match name.as_str() {
-            "line" => {
-                self.next()?.as_open()?;
-                let line = self.next()?.as_immediate()? as usize;
-                self.next()?.as_close()?;
-                return Ok(Hook::Line(line));
-            }
-            "read" => {
-                self.next()?.as_open()?;
-                let reg = self.next()?.as_register(device)?;
-                self.next()?.as_close()?;
-                return Ok(Hook::Read(reg));
-            }
-            "write" => {
-                self.next()?.as_open()?;
-                let reg = self.next()?.as_register(device)?;
-                self.next()?.as_close()?;
-                return Ok(Hook::Write(reg));
+        while let Some(precedence) = self.peek().and_then(precedence) {
+            if precedence < min {
+                break;
             }
-            "unique" => {
-                self.next()?.as_open()?;
-                let reg = self.next()?.as_register(device)?;
-                self.next()?.as_close()?;
-                return Ok(Hook::unique(reg));
+
+            let (_, op) = self.next().expect("peeked token");
+            // NB: all operators are left-associative.
+            let rhs = self.expr(precedence + 1)?;
+            let column = lhs.0;
+            lhs = (column, Node::Binary(op, Box::new(lhs), Box::new(rhs)));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<(usize, Node), ParseError> {
+        let column = self.column();
+
+        match self.peek() {
+            Some(Token::Bang) | Some(Token::Minus) => {
+                let (_, op) = self.next().expect("peeked token");
+                let value = self.unary()?;
+                Ok((column, Node::Unary(op, Box::new(value))))
             }
-            "all" => {
-                self.next()?.as_open()?;
-                let mut hooks = Vec::new();
-                hooks.push(self.as_hook(device)?);
-
-                loop {
-                    match self.next()? {
-                        Token::Comma => {
-                            hooks.push(self.as_hook(device)?);
-                        }
-                        Token::Close => break,
-                        _ => bail!("expected comma or close"),
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<(usize, Node), ParseError> {
+        let (column, token) = match self.next() {
+            Some(next) => next,
+            None => bail_at!(self.end, "expected expression, but got end of input"),
+        };
+
+        let node = match token {
+            Token::Number(number) => Node::Number(number),
+            Token::Ident(ident) => {
+                if self.peek() != Some(&Token::Open) {
+                    return Ok((column, Node::Ident(ident)));
+                }
+
+                self.next();
+                let mut args = Vec::new();
+
+                if self.peek() != Some(&Token::Close) {
+                    args.push(self.argument()?);
+
+                    while self.peek() == Some(&Token::Comma) {
+                        self.next();
+                        args.push(self.argument()?);
                     }
                 }
 
-                return Ok(Hook::All(hooks));
+                self.expect(Token::Close)?;
+                Node::Call(ident, args)
             }
-            "not" => {
-                self.next()?.as_open()?;
-                let inner = self.as_hook(device)?;
-                self.next()?.as_close()?;
-                return Ok(Hook::Not(Box::new(inner)));
+            Token::Open => {
+                let (_, node) = self.expr(0)?;
+                self.expect(Token::Close)?;
+                node
             }
-            "gt" | "lt" | "eq" | "gte" | "lte" => {
-                self.next()?.as_open()?;
-                let reg = self.next()?.as_register(device)?;
-                self.next()?.as_comma()?;
-                let immediate = self.next()?.as_immediate()?;
-                self.next()?.as_close()?;
-
-                let op = match name.as_str() {
+            token => bail_at!(column, "expected expression, but got {}", token),
+        };
+
+        Ok((column, node))
+    }
+
+    /// Parse an argument to a function, which can also be a range like
+    /// `10..20`.
+    fn argument(&mut self) -> Result<(usize, Node), ParseError> {
+        let start = self.expr(0)?;
+
+        if self.peek() != Some(&Token::DotDot) {
+            return Ok(start);
+        }
+
+        self.next();
+        let end = self.expr(0)?;
+        let column = start.0;
+        Ok((column, Node::Range(Box::new(start), Box::new(end))))
+    }
+
+    /// Resolve a node into a hook.
+    fn hook(&self, (column, node): (usize, Node)) -> Result<Hook, ParseError> {
+        let hook = match node {
+            Node::Binary(Token::AmpAmp, a, b) => Hook::all(vec![self.hook(*a)?, self.hook(*b)?]),
+            Node::Binary(Token::PipePipe, a, b) => Hook::any(vec![self.hook(*a)?, self.hook(*b)?]),
+            Node::Unary(Token::Bang, value) => Hook::negate(self.hook(*value)?),
+            Node::Call(name, args) => self.call(column, name, args)?,
+            node => Hook::expr(self.expr_of((column, node))?),
+        };
+
+        Ok(hook)
+    }
+
+    /// Resolve a call to a hook function, like `line(28)`.
+    fn call(
+        &self,
+        column: usize,
+        name: String,
+        args: Vec<(usize, Node)>,
+    ) -> Result<Hook, ParseError> {
+        let arity = match name.as_str() {
+            "all" | "any" => None,
+            "eq" | "ne" | "gt" | "lt" | "gte" | "lte" => Some(2),
+            "jump" | "halt" | "fault" => Some(0),
+            "line" | "before" | "read" | "write" | "unique" | "not" => Some(1),
+            "opcode" | "changed" | "steps" => Some(1),
+            other => bail_at!(column, "no such function `{}`", other),
+        };
+
+        if let Some(arity) = arity {
+            if args.len() != arity {
+                bail_at!(
+                    column,
+                    "`{}` expects {} argument(s), but got {}",
+                    name,
+                    arity,
+                    args.len()
+                );
+            }
+        }
+
+        let mut args = args.into_iter();
+
+        let hook = match name.as_str() {
+            "all" => Hook::all(args.map(|a| self.hook(a)).collect::<Result<_, _>>()?),
+            "any" => Hook::any(args.map(|a| self.hook(a)).collect::<Result<_, _>>()?),
+            "not" => Hook::negate(self.hook(args.next().expect("arity"))?),
+            "line" => match args.next().expect("arity") {
+                (column, Node::Range(start, end)) => {
+                    let range = self.line(*start)?..self.line(*end)?;
+
+                    if range.start >= range.end {
+                        bail_at!(column, "empty range of lines");
+                    }
+
+                    Hook::line_range(range)
+                }
+                line => Hook::line(self.line(line)?),
+            },
+            "opcode" => Hook::op_code(self.op_code(args.next().expect("arity"))?),
+            "jump" => Hook::jump(),
+            "halt" => Hook::halt(),
+            "fault" => Hook::fault(),
+            "changed" => Hook::changed(self.register(args.next().expect("arity"))?),
+            "steps" => Hook::steps(self.steps(args.next().expect("arity"))?),
+            "before" => Hook::before(self.line(args.next().expect("arity"))?),
+            "read" => Hook::read(self.register(args.next().expect("arity"))?),
+            "write" => Hook::write(self.register(args.next().expect("arity"))?),
+            "unique" => Hook::unique(self.register(args.next().expect("arity"))?),
+            name => {
+                let op = match name {
+                    "eq" => Op::Eq,
+                    "ne" => Op::Ne,
                     "gt" => Op::Gt,
                     "lt" => Op::Lt,
-                    "eq" => Op::Eq,
                     "gte" => Op::Gte,
-                    "lte" => Op::Lte,
-                    other => bail!("bad operation: {}", other),
+                    _ => Op::Lte,
                 };
 
-                return Ok(Hook::Op(op, reg, immediate));
+                let a = self.expr_of(args.next().expect("arity"))?;
+                let b = self.expr_of(args.next().expect("arity"))?;
+
+                match (a, b.constant(&self.device.registers)) {
+                    (Expr::Register(reg), Some(value)) => Hook::compare(op, reg, value),
+                    (a, _) => Hook::expr(Expr::Compare(op, Box::new(a), Box::new(b))),
+                }
             }
-            other => bail!("no such function: {}", other),
-        }
+        };
+
+        Ok(hook)
     }
 
-    fn string(&mut self) -> String {
-        let mut buffer = String::new();
+    /// Resolve a node into a constant line number.
+    fn line(&self, node: (usize, Node)) -> Result<usize, ParseError> {
+        self.unsigned(node, "a line number")
+    }
 
-        while let Some(c) = self.it.peek().cloned() {
-            match c {
-                'a'..='z' => {
-                    buffer.push(c);
-                }
-                _ => break,
-            }
+    /// Resolve a node into a constant number of steps.
+    fn steps(&self, node: (usize, Node)) -> Result<usize, ParseError> {
+        self.unsigned(node, "a number of steps")
+    }
+
+    /// Resolve a node into a constant, non-negative number.
+    fn unsigned(&self, (column, node): (usize, Node), what: &str) -> Result<usize, ParseError> {
+        let expr = self.expr_of((column, node))?;
 
-            self.it.next();
+        match expr
+            .constant(&self.device.registers)
+            .and_then(|v| v.as_i64())
+        {
+            Some(value) if value >= 0 => Ok(value as usize),
+            _ => bail_at!(column, "expected {}", what),
         }
+    }
 
-        return buffer;
+    /// Resolve a node into an op code of the instruction set used by the
+    /// loaded program.
+    fn op_code(&self, (column, node): (usize, Node)) -> Result<OpCode, ParseError> {
+        match node {
+            Node::Ident(ref name) => match self.device.decoding_set().decode(name) {
+                Some(op_code) => Ok(op_code),
+                None => bail_at!(column, "no such op code `{}`", name),
+            },
+            _ => bail_at!(column, "expected an op code"),
+        }
     }
 
-    fn immediate(&mut self) -> Result<i64, Error> {
-        let mut buffer = String::new();
+    /// Resolve a node into a register.
+    fn register(&self, (column, node): (usize, Node)) -> Result<usize, ParseError> {
+        match node {
+            Node::Ident(ref name) => match self.device.registers.lookup(name) {
+                Some(reg) => Ok(reg),
+                None => bail_at!(column, "no such register `{}`", name),
+            },
+            _ => bail_at!(column, "expected a register"),
+        }
+    }
 
-        while let Some(c) = self.it.peek().cloned() {
-            match c {
-                '0'..='9' => buffer.push(c),
-                _ => break,
+    /// Resolve a node into a value expression.
+    fn expr_of(&self, (column, node): (usize, Node)) -> Result<Expr, ParseError> {
+        let expr = match node {
+            Node::Number(number) => Expr::Number(number),
+            Node::Ident(ref name) if name == "ip" => Expr::Ip,
+            Node::Ident(name) => match self.device.registers.lookup(&name) {
+                Some(reg) => Expr::Register(reg),
+                None => bail_at!(column, "no such register `{}`", name),
+            },
+            Node::Call(name, _) => {
+                bail_at!(column, "`{}(..)` can't be used as a value", name);
+            }
+            Node::Range(..) => {
+                bail_at!(column, "ranges can only be used in `line(..)`");
             }
+            Node::Unary(op, value) => {
+                let value = Box::new(self.expr_of(*value)?);
 
-            self.it.next();
-        }
+                match op {
+                    Token::Minus => Expr::Neg(value),
+                    _ => Expr::Not(value),
+                }
+            }
+            Node::Binary(op, a, b) => {
+                let b_column = b.0;
+                let a = Box::new(self.expr_of(*a)?);
+                let b = Box::new(self.expr_of(*b)?);
+
+                let op = match op {
+                    Token::Plus => Ok(arithmetic::Op::Add),
+                    Token::Minus => Ok(arithmetic::Op::Sub),
+                    Token::Star => Ok(arithmetic::Op::Mul),
+                    Token::Slash => Ok(arithmetic::Op::Div),
+                    Token::Percent => Ok(arithmetic::Op::Rem),
+                    Token::Amp => Ok(arithmetic::Op::And),
+                    Token::Pipe => Ok(arithmetic::Op::Or),
+                    Token::EqEq => Err(Op::Eq),
+                    Token::BangEq => Err(Op::Ne),
+                    Token::Lt => Err(Op::Lt),
+                    Token::LtEq => Err(Op::Lte),
+                    Token::Gt => Err(Op::Gt),
+                    Token::GtEq => Err(Op::Gte),
+                    op => bail_at!(b_column, "{} can't be used in a value", op),
+                };
+
+                match op {
+                    Ok(op) => Expr::Arithmetic(op, a, b),
+                    Err(op) => Expr::Compare(op, a, b),
+                }
+            }
+        };
+
+        Ok(expr)
+    }
+}
 
-        Ok(str::parse(&buffer)?)
+/// Parse the whole input into a node.
+fn parse_node<'a>(
+    input: &str,
+    device: &'a Device,
+) -> Result<(usize, Node, Parser<'a>), ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        end: input.chars().count() + 1,
+        device,
+    };
+
+    let (column, node) = parser.expr(0)?;
+
+    if let Some((column, token)) = parser.next() {
+        bail_at!(column, "unexpected {}", token);
     }
+
+    Ok((column, node, parser))
 }
 
+/// Parse a breakpoint condition.
 pub fn parse(input: &str, device: &Device) -> Result<Hook, Error> {
-    return Ok(tokenize(input).as_hook(device)?);
+    let (column, node, parser) = parse_node(input, device)?;
+    Ok(parser.hook((column, node))?)
+}
+
+/// Parse an expression which evaluates to a value, like `c + 1`.
+pub fn parse_expr(input: &str, device: &Device) -> Result<Expr, Error> {
+    let (column, node, parser) = parse_node(input, device)?;
+    Ok(parser.expr_of((column, node))?)
+}
+
+/// Parse an expression to print, like `c / 256`.
+///
+/// The expression can be wrapped in the name of a format to print it in, like
+/// `hex(d)`.
+pub fn parse_print(input: &str, device: &Device) -> Result<(Expr, Option<Format>), Error> {
+    let (column, node, parser) = parse_node(input, device)?;
+
+    let format = match node {
+        Node::Call(ref name, ref args) if args.len() == 1 => Format::by_name(name),
+        _ => None,
+    };
+
+    match (format, node) {
+        (Some(format), Node::Call(_, args)) => {
+            let arg = args.into_iter().next().expect("one argument");
+            Ok((parser.expr_of(arg)?, Some(format)))
+        }
+        (_, node) => Ok((parser.expr_of((column, node))?, None)),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse;
+    use super::{parse, parse_print, ParseError};
+    use crate::{device::Device, hook::Action};
+    use crate::{format::Format, Reg};
 
     #[test]
     pub fn test_parse() {
-        use crate::device::Device;
         let device = Device::default();
         parse("all(line(28), line(40))", &device).expect("not parse");
+
+        let display = |input: &str| {
+            parse(input, &device)
+                .expect("not parse")
+                .display(&device)
+                .to_string()
+        };
+
+        assert_eq!(display("line(10..20)"), "line(10..20)");
+        assert_eq!(
+            display("opcode(eqrr) && jump() || changed(c) || steps(1000)"),
+            "any(any(all(opcode(eqrr), jump()), changed(c)), steps(1000))"
+        );
+        assert!(parse("line(20..10)", &device).is_err());
+        assert!(parse("opcode(nope)", &device).is_err());
+        assert!(parse("a == 1..2", &device).is_err());
+    }
+
+    #[test]
+    pub fn test_parse_op_code_of_program() {
+        let mut device = Device::default();
+        assert!(parse("opcode(subi)", &device).is_err());
+
+        device
+            .load("#isa extended\nsubi 0 1 0\n".lines())
+            .expect("not load");
+
+        let hook = parse("opcode(subi)", &device).expect("not parse");
+        assert_eq!(hook.display(&device).to_string(), "opcode(subi)");
+    }
+
+    #[test]
+    pub fn test_parse_print() {
+        let mut device = Device::default();
+        device.load("#reg a=21 d=31\n".lines()).expect("not load");
+
+        let (expr, format) = parse_print("a * 2 + ip", &device).expect("not parse");
+        assert_eq!(expr.eval(&device.registers).ok(), Some(Reg::from(42)));
+        assert_eq!(format, None);
+
+        let (expr, format) = parse_print("hex(d)", &device).expect("not parse");
+        assert_eq!(expr.display(&device.registers).to_string(), "d");
+        assert_eq!(format, Some(Format::Hex));
+
+        assert!(parse_print("line(1)", &device).is_err());
+    }
+
+    #[test]
+    pub fn test_parse_infix() {
+        let mut device = Device::default();
+        device
+            .load("#ip 5\n#reg a=-4 b=16 c=9\nseti 0 0 1\n".lines())
+            .expect("not load");
+
+        let display = |input: &str| {
+            parse(input, &device)
+                .expect("not parse")
+                .display(&device)
+                .to_string()
+        };
+
+        assert_eq!(
+            display("ip == 28 && (a > b || c % 3 == 0)"),
+            "all(ip == 28, any(a > b, c % 3 == 0))"
+        );
+        assert_eq!(
+            display("a == -4 || !(b > 0x10)"),
+            "any(a == -4, not(b > 16))"
+        );
+        assert_eq!(display("(a + b) * 2 - c"), "(a + b) * 2 - c");
+        assert_eq!(display("eq(a, -4)"), "eq(a, -4)");
+        assert_eq!(display("lt(a, b)"), "a < b");
+
+        let mut hook = parse("c % 3 == 0 && a + b == 12", &device).expect("not parse");
+        assert_eq!(hook.test(&device).ok(), Some(Action::Pause));
+
+        let column = |input: &str| {
+            let e = parse(input, &device).expect_err("parsed");
+            e.downcast_ref::<ParseError>()
+                .expect("not a parse error")
+                .column
+        };
+
+        assert_eq!(column("a == (b + 1"), 12);
+        assert_eq!(column("a = 1"), 3);
+        assert_eq!(column("a == x"), 6);
+        assert_eq!(column("a == 1 )"), 8);
+        assert_eq!(column("line(a + line(1))"), 10);
     }
 }
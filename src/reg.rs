@@ -0,0 +1,128 @@
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::{cmp, fmt, str};
+
+/// The value of a register.
+///
+/// Values which fit in 64 bits are stored inline, larger values can only be
+/// produced when using big-integer arithmetic.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Reg {
+    /// A value which fits in 64 bits.
+    Small(i64),
+    /// A value which doesn't fit in 64 bits.
+    Big(Box<BigInt>),
+}
+
+impl Reg {
+    /// Construct a register value from a big integer, storing it inline if it
+    /// fits.
+    pub fn big(value: BigInt) -> Reg {
+        match value.to_i64() {
+            Some(value) => Reg::Small(value),
+            None => Reg::Big(Box::new(value)),
+        }
+    }
+
+    /// Get the value as a 64-bit integer, if it fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Reg::Small(value) => Some(value),
+            Reg::Big(..) => None,
+        }
+    }
+
+    /// Get the value as a big integer.
+    pub fn to_big(&self) -> BigInt {
+        match *self {
+            Reg::Small(value) => BigInt::from(value),
+            Reg::Big(ref value) => (**value).clone(),
+        }
+    }
+
+    /// Get the value truncated to its lowest 64 bits.
+    pub fn truncate(&self) -> i64 {
+        match *self {
+            Reg::Small(value) => value,
+            Reg::Big(ref value) => {
+                let mask = BigInt::from(u64::MAX);
+                // NB: the masked value always fits in an u64.
+                let low = (&**value & mask).to_u64().unwrap_or_default();
+                low as i64
+            }
+        }
+    }
+
+    /// Test if the value is zero.
+    pub fn is_zero(&self) -> bool {
+        *self == Reg::Small(0)
+    }
+}
+
+impl Default for Reg {
+    fn default() -> Reg {
+        Reg::Small(0)
+    }
+}
+
+impl From<i64> for Reg {
+    fn from(value: i64) -> Reg {
+        Reg::Small(value)
+    }
+}
+
+impl From<bool> for Reg {
+    fn from(value: bool) -> Reg {
+        Reg::Small(value as i64)
+    }
+}
+
+impl PartialEq<i64> for Reg {
+    fn eq(&self, other: &i64) -> bool {
+        *self == Reg::Small(*other)
+    }
+}
+
+impl PartialOrd for Reg {
+    fn partial_cmp(&self, other: &Reg) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Reg {
+    fn cmp(&self, other: &Reg) -> cmp::Ordering {
+        match (self, other) {
+            (Reg::Small(a), Reg::Small(b)) => a.cmp(b),
+            (a, b) => a.to_big().cmp(&b.to_big()),
+        }
+    }
+}
+
+impl str::FromStr for Reg {
+    type Err = num_bigint::ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Reg, Self::Err> {
+        if let Ok(value) = str::parse::<i64>(s) {
+            return Ok(Reg::Small(value));
+        }
+
+        Ok(Reg::big(str::parse::<BigInt>(s)?))
+    }
+}
+
+macro_rules! impl_fmt {
+    ($($trait:ident),*) => {
+        $(
+            impl fmt::$trait for Reg {
+                fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    match *self {
+                        Reg::Small(ref value) => fmt::$trait::fmt(value, fmt),
+                        Reg::Big(ref value) => fmt::$trait::fmt(&**value, fmt),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_fmt!(Display, LowerHex, UpperHex, Binary);
@@ -1,70 +1,221 @@
-use crate::{AsReg, Reg};
-use failure::{bail, Error};
-use hashbrown::HashSet;
+use crate::{arithmetic::Arithmetic, bits::BitSet, format::Format, AsReg, Reg};
+use failure::{bail, format_err, Error};
+use hashbrown::HashMap;
 use std::fmt;
 
-#[derive(Debug, Default)]
+/// Default number of registers.
+pub const DEFAULT_LEN: usize = 6;
+
+/// Registers which can be named by a letter, like `a`.
+const LETTERS: &str = "abcdefghijklmnopqrstuvwxyz";
+
+#[derive(Debug, Clone)]
 pub struct Registers {
-    registers: [Reg; 6],
+    registers: Vec<Reg>,
+    /// Values registers are set to when reset.
+    initial: Vec<Reg>,
+    /// Aliases for registers.
+    aliases: HashMap<usize, String>,
+    /// Display formats for registers, which are kept when a program is
+    /// loaded.
+    formats: HashMap<usize, Format>,
+    /// Display format of registers without a format of their own.
+    default_format: Format,
+    /// Arithmetic semantics of the registers.
+    arithmetic: Arithmetic,
     /// Written to registers.
-    written: HashSet<usize>,
+    written: BitSet,
     /// Read from registers.
-    read: HashSet<usize>,
+    read: BitSet,
     /// Last instruction that was executed.
     pub last_ip: Option<usize>,
-    /// Which register contains the current instruction.
-    pub ip: usize,
+    /// Which register contains the current instruction, if the instruction
+    /// pointer is bound to a register.
+    pub ip: Option<usize>,
+    /// The instruction pointer, when it's not bound to a register.
+    counter: Reg,
+}
+
+impl Default for Registers {
+    fn default() -> Registers {
+        Registers {
+            registers: vec![Reg::default(); DEFAULT_LEN],
+            initial: vec![Reg::default(); DEFAULT_LEN],
+            aliases: HashMap::new(),
+            formats: HashMap::new(),
+            default_format: Format::default(),
+            arithmetic: Arithmetic::default(),
+            written: BitSet::new(),
+            read: BitSet::new(),
+            last_ip: None,
+            ip: None,
+            counter: Reg::default(),
+        }
+    }
 }
 
 impl Registers {
     /// Test if the given registry has been written to.
     pub fn is_written(&self, reg: impl AsReg) -> bool {
-        self.written.contains(&reg.as_reg())
+        self.written.contains(reg.as_reg())
     }
 
     /// Test if the given registry has been read from.
     pub fn is_read(&self, reg: impl AsReg) -> bool {
-        self.read.contains(&reg.as_reg())
+        self.read.contains(reg.as_reg())
     }
 
-    /// Access the given register immutably.
+    /// Read the given register as an instruction, tracking it as read.
+    ///
+    /// Hooks, visuals and other tooling should use [`Registers::peek`]
+    /// instead.
     pub fn reg(&mut self, reg: impl AsReg) -> Result<Reg, Error> {
         let index = reg.as_reg();
 
+        // NB: only track valid registers, since the bit set grows to fit.
+        let value = match self.registers.get(index).cloned() {
+            Some(reg) => reg,
+            None => bail!("no such register: {}", index),
+        };
+
         self.read.insert(index);
+        Ok(value)
+    }
+
+    /// Look at the value of the given register, without tracking it as read.
+    pub fn peek(&self, reg: impl AsReg) -> Result<&Reg, Error> {
+        let index = reg.as_reg();
 
-        match self.registers.get(index).cloned() {
+        match self.registers.get(index) {
             Some(reg) => Ok(reg),
             None => bail!("no such register: {}", index),
         }
     }
 
-    /// Access the given register mutably.
-    pub fn reg_mut(&mut self, reg: impl AsReg) -> Result<&mut i64, Error> {
+    /// Set the given register without tracking it as written, normalizing
+    /// the value according to the current arithmetic semantics.
+    pub fn set(&mut self, reg: impl AsReg, value: Reg) -> Result<(), Error> {
         let index = reg.as_reg();
+        let value = self.arithmetic.normalize(value)?;
+
+        match self.registers.get_mut(index) {
+            Some(reg) => *reg = value,
+            None => bail!("no such register: {}", index),
+        }
+
+        Ok(())
+    }
 
+    /// Write to the given register as an instruction, tracking it as written
+    /// and normalizing the value according to the current arithmetic
+    /// semantics.
+    pub fn write(&mut self, reg: impl AsReg, value: Reg) -> Result<(), Error> {
+        let index = reg.as_reg();
+        self.set(index, value)?;
         self.written.insert(index);
+        Ok(())
+    }
+
+    /// Write to the instruction pointer, normalizing the value according to
+    /// the current arithmetic semantics.
+    pub fn write_ip(&mut self, value: Reg) -> Result<(), Error> {
+        match self.ip {
+            Some(ip) => self.write(ip, value),
+            None => {
+                self.counter = self.arithmetic.normalize(value)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Get mutable access to the given register as an instruction, tracking
+    /// it as written.
+    ///
+    /// The value is not normalized, so operations should use
+    /// [`Registers::write`] instead.
+    pub fn reg_mut(&mut self, reg: impl AsReg) -> Result<&mut Reg, Error> {
+        let index = reg.as_reg();
 
         match self.registers.get_mut(index) {
-            Some(reg) => Ok(reg),
+            Some(reg) => {
+                self.written.insert(index);
+                Ok(reg)
+            }
             None => bail!("no such register: {}", index),
         }
     }
 
     pub fn ip(&self) -> Result<usize, Error> {
-        match self.registers.get(self.ip) {
-            Some(reg) => Ok(*reg as usize),
-            None => bail!("no ip register: {}", self.ip),
+        let ip = match self.ip {
+            Some(ip) => match self.registers.get(ip) {
+                Some(reg) => reg,
+                None => bail!("no ip register: {}", ip),
+            },
+            None => &self.counter,
+        };
+
+        match *ip {
+            Reg::Small(ip) => Ok(ip as usize),
+            // NB: out of range, which halts the device.
+            Reg::Big(..) => Ok(usize::MAX),
         }
     }
 
     pub fn ip_mut(&mut self) -> Result<&mut Reg, Error> {
-        match self.registers.get_mut(self.ip) {
-            Some(reg) => Ok(reg),
-            None => bail!("no ip register: {}", self.ip),
+        match self.ip {
+            Some(ip) => match self.registers.get_mut(ip) {
+                Some(reg) => Ok(reg),
+                None => bail!("no ip register: {}", ip),
+            },
+            None => Ok(&mut self.counter),
         }
     }
 
+    /// Advance the instruction pointer to the next instruction.
+    pub fn advance_ip(&mut self) -> Result<(), Error> {
+        use crate::arithmetic::Op;
+
+        let arithmetic = self.arithmetic;
+        let ip = self.ip_mut()?;
+        *ip = arithmetic.eval(Op::Add, ip, &Reg::from(1))?;
+        Ok(())
+    }
+
+    /// Get the current arithmetic semantics.
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// Change the arithmetic semantics, normalizing the value of all registers.
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) -> Result<(), Error> {
+        let registers = self
+            .registers
+            .iter()
+            .map(|r| arithmetic.normalize(r.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let initial = self
+            .initial
+            .iter()
+            .map(|r| arithmetic.normalize(r.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.registers = registers;
+        self.initial = initial;
+        self.arithmetic = arithmetic;
+        Ok(())
+    }
+
+    /// Get the number of registers.
+    pub fn len(&self) -> usize {
+        self.registers.len()
+    }
+
+    /// Test if there are no registers.
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+    }
+
     /// Iterate over all registers.
     pub fn iter(&self) -> impl Iterator<Item = Reg> + '_ {
         self.registers.iter().cloned()
@@ -80,39 +231,326 @@ impl Registers {
         self.written.clear();
         self.read.clear();
         self.last_ip = None;
+        self.counter = Reg::default();
+        self.registers.clone_from(&self.initial);
+    }
+
+    /// Forget all configuration that was set up by a loaded program.
+    ///
+    /// The arithmetic semantics and display formats are kept, see
+    /// [`Registers::forget_removed_formats`].
+    pub fn unload(&mut self) {
+        *self = Registers {
+            formats: std::mem::take(&mut self.formats),
+            default_format: self.default_format,
+            arithmetic: self.arithmetic,
+            ..Registers::default()
+        };
+    }
+
+    /// Change the number of registers.
+    ///
+    /// Added registers are initialized to zero, and aliases and formats of
+    /// removed registers are forgotten. The register bound to the instruction pointer
+    /// can't be removed.
+    pub fn resize(&mut self, len: usize) -> Result<(), Error> {
+        if len == 0 {
+            bail!("there must be at least one register");
+        }
+
+        if let Some(ip) = self.ip.filter(|ip| *ip >= len) {
+            bail!(
+                "can't remove register {}, which is bound to the instruction pointer",
+                ip
+            );
+        }
+
+        if let Some(alias) = self
+            .aliases
+            .values()
+            .find(|a| index_of(a).map(|i| i < len).unwrap_or(false))
+        {
+            bail!("alias `{}` conflicts with the name of a register", alias);
+        }
+
+        self.registers.resize(len, Reg::default());
+        self.initial.resize(len, Reg::default());
+        self.aliases.retain(|reg, _| *reg < len);
+        self.written.truncate(len);
+        self.read.truncate(len);
+        self.forget_removed_formats();
+        Ok(())
+    }
+
+    /// Forget the formats of registers which don't exist, called once a
+    /// program has been loaded.
+    pub fn forget_removed_formats(&mut self) {
+        let len = self.registers.len();
+        self.formats.retain(|reg, _| *reg < len);
+    }
+
+    /// Set the value the given register is set to when reset.
+    pub fn set_initial(&mut self, reg: impl AsReg, value: Reg) -> Result<(), Error> {
+        let index = reg.as_reg();
+        let value = self.arithmetic.normalize(value)?;
 
-        for r in self.registers.iter_mut() {
-            *r = Default::default();
+        match self.initial.get_mut(index) {
+            Some(reg) => *reg = value,
+            None => bail!("no such register: {}", index),
         }
+
+        Ok(())
+    }
+
+    /// Get the value the given register is set to when reset.
+    pub fn initial(&self, reg: impl AsReg) -> Option<&Reg> {
+        self.initial.get(reg.as_reg())
     }
 
-    pub fn name(&self, reg: impl AsReg) -> RegName {
+    /// Set initial values from assignments like `a=1`, as used by the `#reg`
+    /// directive.
+    pub fn parse_initial<'a>(
+        &mut self,
+        assignments: impl Iterator<Item = &'a str>,
+    ) -> Result<(), Error> {
+        let mut any = false;
+
+        for assign in assignments {
+            let mut parts = assign.splitn(2, '=');
+
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => bail!("expected `<reg>=<value>` in `#reg`, but got `{}`", assign),
+            };
+
+            let reg = match self.lookup(name) {
+                Some(reg) => reg,
+                None => bail!("bad register in `#reg`: {}", name),
+            };
+
+            let value = str::parse::<Reg>(value)
+                .map_err(|e| format_err!("bad value in `#reg`: {}: {}", value, e))?;
+
+            self.set_initial(reg, value)?;
+            any = true;
+        }
+
+        if !any {
+            bail!("expected argument to `#reg`");
+        }
+
+        Ok(())
+    }
+
+    /// Give the specified register an alias.
+    pub fn set_alias(&mut self, reg: impl AsReg, alias: &str) -> Result<(), Error> {
+        let index = reg.as_reg();
+
+        if index >= self.registers.len() {
+            bail!("no such register: {}", index);
+        }
+
+        if !is_identifier(alias) {
+            bail!("bad alias `{}`, expected something like `counter`", alias);
+        }
+
+        if alias == "ip" || index_of(alias).map(|i| i < self.len()).unwrap_or(false) {
+            bail!("alias `{}` is reserved", alias);
+        }
+
+        if self.lookup(alias).map(|r| r != index).unwrap_or(false) {
+            bail!("alias `{}` is already in use", alias);
+        }
+
+        self.aliases.insert(index, alias.to_string());
+        Ok(())
+    }
+
+    /// Get the alias of the given register, if it has one.
+    pub fn alias(&self, reg: impl AsReg) -> Option<&str> {
+        self.aliases.get(&reg.as_reg()).map(|a| a.as_str())
+    }
+
+    /// Get the display format of the given register.
+    pub fn format(&self, reg: impl AsReg) -> Format {
+        self.formats
+            .get(&reg.as_reg())
+            .cloned()
+            .unwrap_or(self.default_format)
+    }
+
+    /// Set the display format of the given register.
+    pub fn set_format(&mut self, reg: impl AsReg, format: Format) {
+        self.formats.insert(reg.as_reg(), format);
+    }
+
+    /// Set the display format of all registers, clearing their individual
+    /// formats.
+    pub fn set_default_format(&mut self, format: Format) {
+        self.formats.clear();
+        self.default_format = format;
+    }
+
+    /// Get the display format of registers without a format of their own.
+    pub fn default_format(&self) -> Format {
+        self.default_format
+    }
+
+    /// Look up a register by name or alias.
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        if name == "ip" {
+            return self.ip;
+        }
+
+        if let Some(index) = index_of(name) {
+            if index < self.registers.len() {
+                return Some(index);
+            }
+
+            return None;
+        }
+
+        self.aliases
+            .iter()
+            .find(|(_, alias)| alias.as_str() == name)
+            .map(|(index, _)| *index)
+    }
+
+    pub fn name(&self, reg: impl AsReg) -> RegName<'_> {
         let reg = reg.as_reg();
 
-        let special = if reg == self.ip { Some("ip") } else { None };
+        let special = if self.ip == Some(reg) {
+            Some("ip")
+        } else {
+            None
+        };
 
         RegName {
+            alias: self.aliases.get(&reg).map(|a| a.as_str()),
             special,
-            name: ['a', 'b', 'c', 'd', 'e', 'f'].get(reg).cloned(),
+            index: if reg < self.registers.len() {
+                Some(reg)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Test if the given string is a valid identifier, like `counter`.
+pub(crate) fn is_identifier(input: &str) -> bool {
+    !input.is_empty()
+        && input.chars().enumerate().all(|(i, c)| match c {
+            'a'..='z' | '_' => true,
+            '0'..='9' => i > 0,
+            _ => false,
+        })
+}
+
+/// The name of a register by index alone, like `a` or `r10`.
+pub(crate) fn plain_name(reg: usize) -> RegName<'static> {
+    RegName {
+        alias: None,
+        special: None,
+        index: Some(reg),
+    }
+}
+
+/// Get the index of a register by its name, like `a` or `r10`.
+fn index_of(name: &str) -> Option<usize> {
+    let mut chars = name.chars();
+
+    match (chars.next(), chars.as_str()) {
+        (Some(c), "") => LETTERS.find(c),
+        (Some('r'), rest) if !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()) => {
+            str::parse(rest).ok()
         }
+        _ => None,
     }
 }
 
-pub struct RegName {
+pub struct RegName<'a> {
+    alias: Option<&'a str>,
     special: Option<&'static str>,
-    name: Option<char>,
+    index: Option<usize>,
 }
 
-impl fmt::Display for RegName {
+impl fmt::Display for RegName<'_> {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(alias) = self.alias {
+            return alias.fmt(fmt);
+        }
+
         if let Some(special) = self.special.as_ref() {
             return special.fmt(fmt);
         }
 
-        if let Some(name) = self.name {
-            return name.fmt(fmt);
+        if let Some(index) = self.index {
+            return match LETTERS.get(index..=index) {
+                Some(letter) => letter.fmt(fmt),
+                None => format!("r{}", index).fmt(fmt),
+            };
         }
 
         "?".fmt(fmt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Registers;
+    use crate::Reg;
+
+    #[test]
+    pub fn test_peek_and_set_do_not_track() {
+        let mut registers = Registers::default();
+
+        registers.set(1, Reg::from(42)).expect("not set");
+        assert_eq!(registers.peek(1).ok(), Some(&Reg::from(42)));
+        assert!(!registers.is_read(1));
+        assert!(!registers.is_written(1));
+
+        assert_eq!(registers.reg(1).ok(), Some(Reg::from(42)));
+        *registers.reg_mut(2).expect("no register") = Reg::from(7);
+        assert!(registers.is_read(1));
+        assert!(registers.is_written(2));
+    }
+
+    #[test]
+    pub fn test_out_of_range_is_not_tracked() {
+        let mut registers = Registers::default();
+
+        for &index in &[6, 1_000_000_000, usize::MAX] {
+            assert!(registers.reg(index).is_err());
+            assert!(registers.reg_mut(index).is_err());
+            assert!(registers.write(index, Reg::from(1)).is_err());
+            assert!(!registers.is_read(index));
+            assert!(!registers.is_written(index));
+        }
+
+        // like `addr 0 0 -1`.
+        assert!(registers.write(-1i64, Reg::from(1)).is_err());
+        assert!(!registers.is_written(-1i64));
+    }
+
+    #[test]
+    pub fn test_formats() {
+        use crate::{format::Format, instruction::Instruction, op_code::InstructionSet};
+
+        let mut registers = Registers::default();
+        let set = InstructionSet::default();
+        let inst = Instruction::decode(&set, "bani 2 255 2").expect("not decode");
+
+        registers.set_format(2, Format::Hex);
+        assert_eq!(registers.format(2), Format::Hex);
+        assert_eq!(registers.format(3), Format::Decimal);
+        assert_eq!(inst.human_display(&registers).to_string(), "c  = c & 0xff");
+
+        registers.set_default_format(Format::Bits);
+        assert_eq!(registers.format(2), Format::Bits);
+        assert_eq!(
+            inst.human_display(&registers).to_string(),
+            "c  = c & 0b11111111"
+        );
+    }
+}
@@ -1,12 +1,29 @@
 use crate::{
+    analysis,
+    arithmetic::Arithmetic,
+    assembler::{self, Program},
+    breakpoint::{Breakpoint, Counters},
+    compiler,
     device::Device,
     events::{Event, Events},
+    expr::Expr,
+    flow,
+    format::Format,
+    history::History,
     hook::{Action, Hook},
+    op_code::OpCodeMap,
     parser,
     visuals::Visuals,
+    Reg,
 };
 use failure::{bail, Error};
-use std::{borrow::Cow, io};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Write as _},
+    time::{Duration, Instant},
+};
 use termion::{
     event::Key,
     raw::{IntoRawMode, RawTerminal},
@@ -19,6 +36,21 @@ use tui::{
     style::{Color, Modifier, Style},
 };
 
+/// Number of values kept in the history of each register.
+const HISTORY_LEN: usize = 256;
+
+/// Number of values shown in the sparklines of the registers pane.
+const SPARKLINE_LEN: usize = 12;
+
+/// Number of lanes used to draw jumps next to the instructions pane.
+const GUTTER_LANES: usize = 6;
+
+/// Number of messages kept in the log.
+const LOG_LEN: usize = 1000;
+
+/// Height of the log pane, including its borders.
+const LOG_HEIGHT: u16 = 12;
+
 type TerminalType = tui::Terminal<TermionBackend<AlternateScreen<RawTerminal<io::Stdout>>>>;
 
 pub enum Message {
@@ -28,6 +60,24 @@ pub enum Message {
 }
 
 impl Message {
+    /// The text of the message.
+    fn text(&self) -> &str {
+        match *self {
+            Message::Error(ref m) | Message::Info(ref m) | Message::Bold(ref m) => m,
+        }
+    }
+
+    /// The style the message is drawn with.
+    fn style(&self) -> Style {
+        match *self {
+            Message::Error(..) => Style::default().fg(Color::Red),
+            Message::Info(..) => Style::default().fg(Color::White),
+            Message::Bold(..) => Style::default()
+                .modifier(Modifier::Underline)
+                .fg(Color::White),
+        }
+    }
+
     pub fn error(error: impl Into<Cow<'static, str>>) -> Message {
         Message::Error(error.into())
     }
@@ -41,15 +91,206 @@ impl Message {
     }
 }
 
+/// An expression which is evaluated and shown every redraw.
+struct Watch {
+    expr: Expr,
+    /// The value when the device last stopped.
+    last: Option<Reg>,
+}
+
+impl Watch {
+    /// Remember the current value, to highlight when it changes.
+    fn snapshot(&mut self, device: &Device) {
+        self.last = self.expr.eval(&device.registers).ok();
+    }
+}
+
+/// A message in the log, with when it was logged.
+struct Entry {
+    /// Time since the debugger started.
+    elapsed: Duration,
+    /// The step count of the device.
+    step: usize,
+    message: Message,
+}
+
+impl Entry {
+    /// The line shown for the entry, like `   1.250       1,024 message`.
+    fn line(&self) -> String {
+        format!(
+            "{:>8.3} {:>11} {}",
+            self.elapsed.as_secs_f64(),
+            thousands(self.step),
+            self.message.text()
+        )
+    }
+
+    /// Write the entry to a log file.
+    fn write(&self, out: &mut impl io::Write) -> io::Result<()> {
+        let kind = match self.message {
+            Message::Error(..) => "error",
+            Message::Info(..) => "info",
+            Message::Bold(..) => "bold",
+        };
+
+        writeln!(
+            out,
+            "{:.3} {} {} {}",
+            self.elapsed.as_secs_f64(),
+            self.step,
+            kind,
+            self.message.text()
+        )
+    }
+}
+
+/// The message log, which keeps the last `LOG_LEN` messages.
+struct Log {
+    started: Instant,
+    entries: VecDeque<Entry>,
+    /// Number of entries scrolled up from the most recent one.
+    scroll: usize,
+    /// File which new entries are written to, and its path.
+    file: Option<(String, BufWriter<File>)>,
+}
+
+impl Log {
+    fn new() -> Log {
+        Log {
+            started: Instant::now(),
+            entries: VecDeque::new(),
+            scroll: 0,
+            file: None,
+        }
+    }
+
+    /// Move all pending messages into the log.
+    fn append(&mut self, messages: &mut Vec<Message>, step: usize) {
+        if messages.is_empty() {
+            return;
+        }
+
+        let elapsed = self.started.elapsed();
+
+        for message in messages.drain(..) {
+            let entry = Entry {
+                elapsed,
+                step,
+                message,
+            };
+
+            if let Some((path, mut file)) = self.file.take() {
+                match entry.write(&mut file) {
+                    Ok(()) => self.file = Some((path, file)),
+                    Err(e) => self.push(Entry {
+                        elapsed,
+                        step,
+                        message: Message::error(format!("stopped logging to `{}`: {}", path, e)),
+                    }),
+                }
+            }
+
+            self.push(entry);
+        }
+    }
+
+    /// Push a single entry, keeping at most `LOG_LEN` of them.
+    fn push(&mut self, entry: Entry) {
+        if self.entries.len() >= LOG_LEN {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(entry);
+
+        // NB: keep the same entries visible when scrolled up.
+        if self.scroll > 0 {
+            let max = self.entries.len().saturating_sub(1);
+            self.scroll = usize::min(self.scroll + 1, max);
+        }
+    }
+
+    /// Start writing the log to the given path, beginning with the messages
+    /// which are already in the log.
+    fn open(&mut self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        for entry in &self.entries {
+            entry.write(&mut file)?;
+        }
+
+        file.flush()?;
+        self.file = Some((path.to_string(), file));
+        Ok(())
+    }
+
+    /// Flush entries written to the log file, which is closed if that fails.
+    fn flush(&mut self, messages: &mut Vec<Message>) {
+        if let Some((path, mut file)) = self.file.take() {
+            match file.flush() {
+                Ok(()) => self.file = Some((path, file)),
+                Err(e) => {
+                    messages.push(Message::error(format!(
+                        "stopped logging to `{}`: {}",
+                        path, e
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Scroll up by the given number of entries.
+    fn scroll_up(&mut self, n: usize) {
+        let max = self.entries.len().saturating_sub(1);
+        self.scroll = usize::min(self.scroll.saturating_add(n), max);
+    }
+
+    /// Scroll down by the given number of entries.
+    fn scroll_down(&mut self, n: usize) {
+        self.scroll = self.scroll.saturating_sub(n);
+    }
+
+    /// The entries which are visible in a pane of the given height.
+    fn visible(&self, height: usize) -> impl Iterator<Item = &Entry> {
+        let end = self.entries.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+        self.entries.range(start..end)
+    }
+}
+
+/// Format a number with thousands separators, like `8,401,233`.
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+
+        out.push(c);
+    }
+
+    out
+}
+
 pub struct Terminal {
     interactive: bool,
-    hooks: Vec<Hook>,
+    breakpoints: Vec<Breakpoint>,
+    /// Named counters, incremented by breakpoints.
+    counters: Counters,
+    /// Indexes of the breakpoints which caused the last pause.
+    triggered: Vec<usize>,
+    watches: Vec<Watch>,
+    /// History of register values.
+    history: History,
     events: Events,
     terminal: Option<TerminalType>,
     input: String,
     /// Last command run.
     last: Option<String>,
+    /// Messages which have not been moved to the log yet.
     messages: Vec<Message>,
+    log: Log,
     scroll: usize,
     /// If we should use human-readable decoding for instructions.
     human_decoding: bool,
@@ -65,12 +306,17 @@ impl Terminal {
 
         Self {
             interactive: false,
-            hooks: Vec::new(),
+            breakpoints: Vec::new(),
+            counters: Counters::new(),
+            triggered: Vec::new(),
+            watches: Vec::new(),
+            history: History::new(HISTORY_LEN),
             events: Events::new(),
             terminal: None,
             input: String::new(),
             last: None,
             messages,
+            log: Log::new(),
             scroll: 0,
             human_decoding: true,
             noninteractive_step: 1_000_000,
@@ -82,8 +328,11 @@ impl Terminal {
         self
     }
 
-    pub fn hook(mut self, hook: Hook) -> Self {
-        self.hooks.push(hook);
+    /// Add a breakpoint, which can be any [`Condition`].
+    ///
+    /// [`Condition`]: crate::hook::Condition
+    pub fn hook(mut self, hook: impl Into<Hook>) -> Self {
+        self.breakpoints.push(Breakpoint::new(hook.into()));
         self
     }
 
@@ -94,6 +343,28 @@ impl Terminal {
         messages.push(Message::info(
             "  load <path> - load an elfcode program from the given path.",
         ));
+        messages.push(Message::info(
+            "  opcodes [path] - load the mapping of numeric op codes from the given path, or",
+        ));
+        messages.push(Message::info("    list the current mapping."));
+        messages.push(Message::info(
+            "  infer <path> [output] - infer the mapping of numeric op codes from the samples",
+        ));
+        messages.push(Message::info(
+            "    at the given path, and either use it or write it to [output].",
+        ));
+        messages.push(Message::info(
+            "  assemble, asm <path> [output] - assemble the program at the given path, and",
+        ));
+        messages.push(Message::info(
+            "    either load it or write it as plain elfcode to [output].",
+        ));
+        messages.push(Message::info(
+            "  compile <path> [output] - compile the program at the given path, and either",
+        ));
+        messages.push(Message::info(
+            "    load it or write it as plain elfcode to [output].",
+        ));
         messages.push(Message::info(
             "  reset - reset the device back to its original state.",
         ));
@@ -101,17 +372,57 @@ impl Terminal {
             "  break, b <expr> - break when the given expression holds true.",
         ));
         messages.push(Message::info(
-            "    <expr> can be one of: line(<line>), read(<reg>), write(<reg>), not(<expr>),",
+            "    <expr> can be one of: line(<line>), before(<line>), read(<reg>), write(<reg>),",
+        ));
+        messages.push(Message::info(
+            "    not(<expr>), all(<expr1>[, ..]), any(<expr1>[, ..]), unique(<reg>),",
+        ));
+        messages.push(Message::info(
+            "    <op>(<reg>, <value>), opcode(<name>), jump(), changed(<reg>), steps(<n>),",
+        ));
+        messages.push(Message::info(
+            "    line(<start>..<end>), halt(), or fault().",
         ));
         messages.push(Message::info(
-            "    all(<expr1>[, <expr2>]), unique(<reg>), or <op>(<reg>, <value>).",
+            "    line(<line>) breaks after <line> has run, before(<line>) breaks before it runs.",
+        ));
+        messages.push(Message::info(
+            "    Breakpoints are tested after every step, so before(0) doesn't fire at the start.",
         ));
         messages.push(Message::info("    <reg> is a registry, like `a` or `ip`."));
         messages.push(Message::info(
             "    <value> is a registry value, like `42` or `100000`.",
         ));
         messages.push(Message::info(
-            "    <op> can be one of `eq`, `lt`, `lte`, `gt`, or `gte`.",
+            "    <op> can be one of `eq`, `ne`, `lt`, `lte`, `gt`, or `gte`.",
+        ));
+        messages.push(Message::info(
+            "    expressions can also be written infix, like `line(28) && (a > b || c % 3 == 0)`,",
+        ));
+        messages.push(Message::info(
+            "    using `+ - * / % & |`, comparisons, `&&`, `||`, `!`, and hex like `0x1f`.",
+        ));
+        messages.push(Message::info(
+            "  break, b <expr> do <action>[; ..] - run actions when <expr> holds, one of:",
+        ));
+        messages.push(Message::info(
+            "    log \"<format>\", log >> <path> \"<format>\", set <reg> <expr>, count <name>,",
+        ));
+        messages.push(Message::info(
+            "    or continue. <format> can contain expressions in braces, like `c = {c + 1}`.",
+        ));
+        messages.push(Message::info(
+            "  trace <expr> [do <action>[; ..]] - like break, but always continues. Logs every",
+        ));
+        messages.push(Message::info("    register if no actions are given."));
+        messages.push(Message::info(
+            "  tbreak <expr> [do <action>[; ..]] - like break, but removed after it fired once.",
+        ));
+        messages.push(Message::info(
+            "  ignore <index> <n> - ignore the next <n> hits of a breakpoint.",
+        ));
+        messages.push(Message::info(
+            "  enable, disable [index] - enable or disable a breakpoint, keeping its state.",
         ));
         messages.push(Message::info(
             "  clear, cl [index] - clear breakpoint, if [index] is blank removed the last one.",
@@ -119,6 +430,37 @@ impl Terminal {
         messages.push(Message::info(
             "  inspect [index] - inspect the state of a breakpoint.",
         ));
+        messages.push(Message::info(
+            "  watch <expr> - show the value of an expression, like `c % 256`, in the watches",
+        ));
+        messages.push(Message::info(
+            "    pane. Values which changed since the last stop are highlighted.",
+        ));
+        messages.push(Message::info(
+            "  unwatch [index] - remove a watch, if [index] is blank removes the last one.",
+        ));
+        messages.push(Message::info(
+            "  print, eval, p <expr> - print the value of an expression in decimal, hex, and",
+        ));
+        messages.push(Message::info(
+            "    binary, like `a * 2 + ip`. Wrap it in hex(..), bin(..), or dec(..) to pick one.",
+        ));
+        messages.push(Message::info(
+            "  format [<reg>|all <format>] - show or change how register values are displayed,",
+        ));
+        messages.push(Message::info(
+            "    one of: dec, unsigned, hex, bin, or bits. Also applies to immediates.",
+        ));
+        messages.push(Message::info(
+            "  history <reg> [count] - list the last values of a register, and the steps",
+        ));
+        messages.push(Message::info("    where they were seen."));
+        messages.push(Message::info(
+            "  log file <path> - write the log to a file, starting with the messages in it.",
+        ));
+        messages.push(Message::info(
+            "  log close - stop writing the log to a file. log clear - clear the log.",
+        ));
         messages.push(Message::info("  step, s - run a single instruction."));
         messages.push(Message::info(
             "  continue, c - continue running in non-interactive mode.",
@@ -126,6 +468,10 @@ impl Terminal {
         messages.push(Message::info(
             "  set <reg> <value> - set the register <reg> to the given value <value>.",
         ));
+        messages.push(Message::info(
+            "  mode [name] - show or change the arithmetic mode, one of: checked, wrapping,",
+        ));
+        messages.push(Message::info("    u32, or big."));
         messages.push(Message::bold("Keys:"));
         messages.push(Message::info(
             "  <up>|<down> - scroll the instructions window up and down.",
@@ -133,6 +479,10 @@ impl Terminal {
         messages.push(Message::info(
             "  <F1> - toggle between original and human decoding of instructions.",
         ));
+        messages.push(Message::info(
+            "  <F2> - cycle the display format of all registers.",
+        ));
+        messages.push(Message::info("  <PgUp>, <PgDn> - scroll the log."));
         messages.push(Message::info("  <q> - quit when in non-interactive mode."));
         messages.push(Message::info("  <p> - pause when in non-interactive mode."));
     }
@@ -143,8 +493,12 @@ impl Terminal {
         interactive: bool,
         human_decoding: bool,
         scroll: &mut usize,
-        messages: &mut Vec<Message>,
-        hooks: &mut Vec<Hook>,
+        log: &Log,
+        breakpoints: &mut Vec<Breakpoint>,
+        counters: &Counters,
+        triggered: &[usize],
+        watches: &[Watch],
+        history: &History,
         input: &mut String,
         device: &Device,
     ) -> Result<(), Error> {
@@ -153,8 +507,30 @@ impl Terminal {
         let mut instruction_list = Vec::new();
         let mut register_list = Vec::new();
         let mut breakpoints_list = Vec::new();
+        let mut watch_list = Vec::new();
         let mut device_list = Vec::new();
 
+        // the instruction which will run next.
+        let next = if device.halted {
+            None
+        } else {
+            device.registers.ip().ok()
+        };
+
+        // registers as they will be after the next instruction ran, used to
+        // show the values of the instruction which follows it.
+        let after = next
+            .and_then(|ip| device.instructions.get(ip))
+            .and_then(|inst| inst.simulate(&device.registers).ok());
+
+        let following = after
+            .as_ref()
+            .and_then(|r| r.ip().ok())
+            .filter(|ip| Some(*ip) != next);
+
+        let edges = flow::edges(device);
+        let gutter = flow::gutter(&edges, device.instructions.len(), GUTTER_LANES);
+
         for (line, inst) in device.instructions[*scroll..]
             .iter()
             .enumerate()
@@ -168,12 +544,37 @@ impl Terminal {
                 .unwrap_or(false);
 
             let l = if human_decoding {
-                format!("{:<3}: {}", line, inst.human_display(&device.registers))
+                let previous = line.checked_sub(1).and_then(|l| device.instructions.get(l));
+
+                match after.as_ref() {
+                    _ if next == Some(line) => inst
+                        .human_display(&device.registers)
+                        .at(line, previous)
+                        .with_values()
+                        .to_string(),
+                    Some(after) if following == Some(line) => inst
+                        .human_display(after)
+                        .at(line, previous)
+                        .with_values()
+                        .to_string(),
+                    _ => inst
+                        .human_display(&device.registers)
+                        .at(line, previous)
+                        .to_string(),
+                }
             } else {
-                format!("{:<3}: {}", line, inst.display())
+                inst.display().to_string()
+            };
+
+            let l = match inst.comment.as_ref() {
+                Some(comment) => format!("{}{:<3}: {:<20} ; {}", gutter[line], line, l, comment),
+                None => format!("{}{:<3}: {}", gutter[line], line, l),
             };
 
-            if standout {
+            if next == Some(line) {
+                let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+                instruction_list.push(Text::Styled(l.into(), style));
+            } else if standout {
                 let style = Style::default().fg(Color::Black).bg(Color::White);
                 instruction_list.push(Text::Styled(l.into(), style));
             } else {
@@ -189,8 +590,17 @@ impl Terminal {
             };
 
             let l = if human_decoding {
-                format!("{:<2}{}= {}", device.registers.name(reg), mark, value)
+                let value = device.registers.format(reg).display(&value);
+                let spark = history.sparkline(reg, SPARKLINE_LEN);
+                format!(
+                    "{:<2}{}= {:<12} {}",
+                    device.registers.name(reg),
+                    mark,
+                    value.to_string(),
+                    spark
+                )
             } else {
+                let value = device.registers.format(reg).display(&value);
                 format!("{:<2}{}= {}", reg, mark, value)
             };
 
@@ -202,35 +612,110 @@ impl Terminal {
             }
         }
 
-        for (index, hook) in hooks.iter().enumerate() {
-            breakpoints_list.push(Text::raw(format!(
-                "{:<2}: {}",
+        for (index, breakpoint) in breakpoints.iter().enumerate() {
+            let mut state = vec![format!("hits: {}", breakpoint.hits)];
+
+            if breakpoint.ignore > 0 {
+                state.push(format!("ignore: {}", breakpoint.ignore));
+            }
+
+            if breakpoint.temporary {
+                state.push(String::from("temporary"));
+            }
+
+            let l = format!(
+                "{:<2}: {} ({})",
                 index,
-                hook.display(&device)
-            )));
+                breakpoint.display(&device),
+                state.join(", ")
+            );
+
+            if triggered.contains(&index) {
+                let style = Style::default().fg(Color::Black).bg(Color::Red);
+                breakpoints_list.push(Text::styled(l, style));
+            } else if breakpoint.enabled {
+                breakpoints_list.push(Text::raw(l));
+            } else {
+                let style = Style::default().fg(Color::DarkGray);
+                breakpoints_list.push(Text::styled(l, style));
+            }
         }
 
-        device_list.push(Text::raw(format!("Count: {}", device.count)));
-        device_list.push(Text::raw(format!("Unique: {}", device.unique.len())));
+        for (name, count) in counters {
+            breakpoints_list.push(Text::raw(format!("{} = {}", name, count)));
+        }
 
-        terminal.draw(|mut f| {
-            let mut constraints = Vec::new();
-            constraints.push(Constraint::Min(0));
+        for (index, watch) in watches.iter().enumerate() {
+            let value = watch.expr.eval(&device.registers);
+
+            let l = match value {
+                Ok(ref value) => format!(
+                    "{:<2}: {} = {}",
+                    index,
+                    watch.expr.display(&device.registers),
+                    value
+                ),
+                Err(ref e) => format!(
+                    "{:<2}: {} = <{}>",
+                    index,
+                    watch.expr.display(&device.registers),
+                    e
+                ),
+            };
 
-            if device.halted {
-                messages.push(Message::bold("device is halted"));
-                messages.push(Message::info("use `reset` to unhalt"));
+            if value.ok() != watch.last {
+                let style = Style::default().fg(Color::Black).bg(Color::White);
+                watch_list.push(Text::styled(l, style));
+            } else {
+                watch_list.push(Text::raw(l));
             }
+        }
 
-            if !interactive {
-                messages.push(Message::bold(
-                    "running in non-interactive mode, press `p` to pause or `q` to quit",
-                ));
+        device_list.push(Text::raw(format!("Count: {}", device.count)));
+        device_list.push(Text::raw(format!("Unique: {}", device.unique.len())));
+        device_list.push(Text::raw(format!(
+            "Mode: {}",
+            device.registers.arithmetic()
+        )));
+
+        if device.registers.ip.is_none() {
+            if let Ok(ip) = device.registers.ip() {
+                device_list.push(Text::raw(format!("IP: {}", ip)));
             }
+        }
 
-            for _ in 0..messages.len() {
-                constraints.push(Constraint::Length(1));
-            }
+        // NB: status is shown with the device, so that it doesn't fill up the
+        // log.
+        if let Some(fault) = device.fault.as_ref() {
+            let m = Message::error(fault.to_string());
+            device_list.push(Text::styled(m.text().to_string(), m.style()));
+        }
+
+        if device.halted {
+            let m = Message::bold("device is halted, use `reset` to unhalt");
+            device_list.push(Text::styled(m.text().to_string(), m.style()));
+        }
+
+        if !interactive {
+            let m = Message::bold("running, press `p` to pause or `q` to quit");
+            device_list.push(Text::styled(m.text().to_string(), m.style()));
+        }
+
+        let log_list = log
+            .visible(LOG_HEIGHT as usize - 2)
+            .map(|e| Text::styled(e.line(), e.message.style()))
+            .collect::<Vec<_>>();
+
+        let log_title = match (log.scroll, log.file.as_ref()) {
+            (0, None) => String::from("Log (`PgUp` to scroll)"),
+            (0, Some((path, _))) => format!("Log (`PgUp` to scroll, writing to `{}`)", path),
+            (n, _) => format!("Log ({} more, `PgDn` to scroll)", n),
+        };
+
+        terminal.draw(|mut f| {
+            let mut constraints = Vec::new();
+            constraints.push(Constraint::Min(0));
+            constraints.push(Constraint::Length(LOG_HEIGHT));
 
             if interactive {
                 constraints.push(Constraint::Length(1));
@@ -241,7 +726,7 @@ impl Terminal {
                 .constraints(constraints)
                 .split(f.size());
 
-            let (top, mut horizontal) = match horizontal.split_first() {
+            let (top, horizontal) = match horizontal.split_first() {
                 Some(d) => d,
                 None => panic!("bad horizontal layout"),
             };
@@ -271,9 +756,14 @@ impl Terminal {
                     .direction(Direction::Vertical)
                     .constraints(
                         [
-                            Constraint::Length(8),
+                            // NB: leave room for the other panes with many
+                            // registers.
+                            Constraint::Length(u16::min(
+                                usize::max(device.registers.len(), watches.len()) as u16 + 2,
+                                right.height / 2,
+                            )),
                             Constraint::Min(0),
-                            Constraint::Length(4),
+                            Constraint::Length(device_list.len() as u16 + 2),
                         ]
                         .as_ref(),
                     )
@@ -284,9 +774,29 @@ impl Terminal {
                     _ => panic!("bad horizontal layout"),
                 };
 
+                let (registers, watches) = if watches.is_empty() {
+                    (top, None)
+                } else {
+                    let layout = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Min(0), Constraint::Percentage(60)].as_ref())
+                        .split(top);
+
+                    match layout.as_slice() {
+                        &[left, right] => (left, Some(right)),
+                        _ => panic!("bad horizontal layout"),
+                    }
+                };
+
                 List::new(register_list.into_iter())
                     .block(Block::default().borders(Borders::ALL).title("Registers"))
-                    .render(&mut f, top);
+                    .render(&mut f, registers);
+
+                if let Some(watches) = watches {
+                    List::new(watch_list.into_iter())
+                        .block(Block::default().borders(Borders::ALL).title("Watches"))
+                        .render(&mut f, watches);
+                }
 
                 List::new(breakpoints_list.into_iter())
                     .block(Block::default().borders(Borders::ALL).title("Breakpoints"))
@@ -297,41 +807,14 @@ impl Terminal {
                     .render(&mut f, bottom);
             }
 
-            for message in messages.drain(..) {
-                let (current, rest) = match horizontal.split_first() {
-                    Some(d) => d,
-                    None => panic!("bad horizontal layout"),
-                };
-
-                horizontal = rest;
-
-                let (style, m) = match message {
-                    Message::Error(m) => (Style::default().fg(Color::Red), m),
-                    Message::Info(m) => (Style::default().fg(Color::White), m),
-                    Message::Bold(m) => (
-                        Style::default()
-                            .modifier(Modifier::Underline)
-                            .fg(Color::White),
-                        m,
-                    ),
-                };
-
-                let layout = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints(
-                        [
-                            Constraint::Length(1),
-                            Constraint::Min(0),
-                            Constraint::Length(1),
-                        ]
-                        .as_ref(),
-                    )
-                    .split(*current);
+            let (current, horizontal) = match horizontal.split_first() {
+                Some(d) => d,
+                None => panic!("bad horizontal layout"),
+            };
 
-                Paragraph::new([Text::Raw(m)].iter())
-                    .style(style)
-                    .render(&mut f, layout[1]);
-            }
+            List::new(log_list.into_iter())
+                .block(Block::default().borders(Borders::ALL).title(&log_title))
+                .render(&mut f, *current);
 
             if interactive {
                 let (current, _) = match horizontal.split_first() {
@@ -384,7 +867,12 @@ impl Visuals for Terminal {
             ref mut input,
             ref mut last,
             ref mut messages,
-            ref mut hooks,
+            ref mut log,
+            ref mut breakpoints,
+            ref mut counters,
+            ref mut triggered,
+            ref mut watches,
+            ref mut history,
             ref mut scroll,
             ref mut human_decoding,
             ref mut noninteractive_step,
@@ -396,6 +884,12 @@ impl Visuals for Terminal {
             None => bail!("terminal not configured"),
         };
 
+        // NB: while tracing, every write is recorded with the step which made
+        // it, and not only the values at each redraw.
+        if breakpoints.iter().any(|b| b.enabled && b.is_trace()) {
+            history.record_writes(device);
+        }
+
         let draw = *interactive || device.count % *noninteractive_step == 0;
 
         // adjust scroll if needed.
@@ -406,7 +900,7 @@ impl Visuals for Terminal {
                     *scroll = last_ip;
                 } else {
                     let size = terminal.size()?;
-                    let height = (size.height - 4) as usize;
+                    let height = size.height.saturating_sub(LOG_HEIGHT + 4) as usize;
 
                     if last_ip > *scroll + height {
                         *scroll = last_ip;
@@ -416,28 +910,32 @@ impl Visuals for Terminal {
         }
 
         loop {
+            log.append(messages, device.count);
+
             let draw = *interactive || device.count % *noninteractive_step == 0;
 
             if draw {
+                // NB: only recorded when drawing, since recording on every
+                // step slows down running.
+                history.record(device);
+
                 Self::draw_internal(
                     terminal,
                     *interactive,
                     *human_decoding,
                     scroll,
-                    messages,
-                    hooks,
+                    log,
+                    breakpoints,
+                    counters,
+                    triggered,
+                    watches,
+                    history,
                     input,
                     device,
                 )?;
             }
 
             if !*interactive {
-                // if device is halted, make interactive.
-                if device.halted {
-                    *interactive = true;
-                    continue;
-                }
-
                 match self.events.try_next()? {
                     Some(e) => match e {
                         Event::Input(Key::Char('q')) => {
@@ -447,30 +945,96 @@ impl Visuals for Terminal {
                             *interactive = true;
                             continue;
                         }
-                        e => generic_handle(
-                            e,
-                            human_decoding,
-                            device.instructions.len(),
-                            scroll,
-                            messages,
-                        ),
+                        e => generic_handle(e, human_decoding, device, scroll, log, messages),
                     },
                     None => {}
                 }
 
-                for b in hooks.iter_mut() {
-                    if let Action::Pause = b.test(device)? {
-                        *interactive = true;
+                let mut lines = Vec::new();
+                triggered.clear();
+                // temporary breakpoints which fired, and should be removed.
+                let mut expired = Vec::new();
+
+                for (index, b) in breakpoints.iter_mut().enumerate() {
+                    let result = match b.test(device) {
+                        Ok(Action::Pause) => {
+                            if b.temporary {
+                                expired.push(index);
+                            }
+
+                            b.fire(device, counters, &mut lines)
+                        }
+                        Ok(Action::None) => Ok(false),
+                        Err(e) => Err(e),
+                    };
+
+                    match result {
+                        Ok(true) => {
+                            messages.push(Message::bold(format!(
+                                "{}: {} at step {}",
+                                index,
+                                b.hook.reason(device),
+                                thousands(device.count)
+                            )));
+                            triggered.push(index);
+                            *interactive = true;
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            messages.push(Message::error(format!(
+                                "breakpoint {} failed: {}",
+                                index, e
+                            )));
+                            *interactive = true;
+                        }
+                    }
+                }
+
+                messages.extend(lines.into_iter().map(Message::info));
+
+                for index in expired.into_iter().rev() {
+                    breakpoints.remove(index);
+                    triggered.retain(|t| *t != index);
+
+                    for t in triggered.iter_mut().filter(|t| **t > index) {
+                        *t -= 1;
+                    }
+                }
+
+                // NB: breakpoints are tested on the step which halted the
+                // device, so that `halt()` and `fault()` can fire.
+                if device.halted {
+                    if triggered.is_empty() && device.fault.is_none() {
+                        messages.push(Message::bold(format!(
+                            "program finished after {} steps",
+                            thousands(device.count)
+                        )));
                     }
+
+                    *interactive = true;
                 }
 
                 if !*interactive {
+                    log.append(messages, device.count);
                     return Ok(false);
                 }
 
                 continue;
             }
 
+            // NB: the device is paused or halted, so make logged files
+            // visible.
+            log.flush(messages);
+
+            for (index, b) in breakpoints.iter_mut().enumerate() {
+                if let Err(e) = b.flush() {
+                    messages.push(Message::error(format!(
+                        "breakpoint {} failed: {}",
+                        index, e
+                    )));
+                }
+            }
+
             loop {
                 match self.events.next()? {
                     Event::Input(Key::Backspace) => {
@@ -492,6 +1056,8 @@ impl Visuals for Terminal {
                             }
                         };
 
+                        messages.push(Message::info(format!("> {}", last)));
+
                         let mut it = last.splitn(2, " ");
 
                         match it.next() {
@@ -500,11 +1066,16 @@ impl Visuals for Terminal {
                                 break;
                             }
                             Some("reset") => {
-                                for h in hooks.iter_mut() {
-                                    h.reset();
+                                for b in breakpoints.iter_mut() {
+                                    b.reset();
                                 }
 
+                                counters.clear();
+                                history.clear();
+                                triggered.clear();
+
                                 device.reset();
+                                history.record(device);
                                 break;
                             }
                             Some("c") | Some("continue") => {
@@ -514,6 +1085,10 @@ impl Visuals for Terminal {
                                     break;
                                 }
 
+                                for w in watches.iter_mut() {
+                                    w.snapshot(device);
+                                }
+
                                 *interactive = false;
                                 return Ok(false);
                             }
@@ -526,9 +1101,15 @@ impl Visuals for Terminal {
                                     break;
                                 }
 
+                                for w in watches.iter_mut() {
+                                    w.snapshot(device);
+                                }
+
                                 return Ok(false);
                             }
-                            Some("b") | Some("break") => {
+                            Some("b") | Some("break") | Some("tbreak") => {
+                                let temporary = last.starts_with("tbreak");
+
                                 let condition = match it.next() {
                                     Some(condition) => condition,
                                     None => {
@@ -537,25 +1118,147 @@ impl Visuals for Terminal {
                                     }
                                 };
 
-                                if let Ok(hook) = parse_hook(device, condition, messages) {
-                                    hooks.push(hook);
+                                if let Ok(mut b) =
+                                    parse_input(device, condition, messages, Breakpoint::parse)
+                                {
+                                    b.temporary = temporary;
+                                    breakpoints.push(b);
                                 }
 
                                 break;
                             }
-                            Some("load") => {
-                                let it = it.flat_map(|s| s.split(" "));
-                                load_command(device, it, messages);
-                                break;
-                            }
+                            Some("trace") => {
+                                let condition = match it.next() {
+                                    Some(condition) => condition,
+                                    None => {
+                                        messages.push(Message::error("missing trace condition!"));
+                                        break;
+                                    }
+                                };
+
+                                if let Ok(b) =
+                                    parse_input(device, condition, messages, Breakpoint::trace)
+                                {
+                                    breakpoints.push(b);
+                                }
+
+                                break;
+                            }
+                            Some("load") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                load_command(device, it, messages);
+                                history.clear();
+                                history.record(device);
+                                break;
+                            }
+                            Some("opcodes") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                opcodes_command(device, it, messages);
+                                break;
+                            }
+                            Some("infer") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                infer_command(device, it, messages);
+                                break;
+                            }
+                            Some("assemble") | Some("asm") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                build_command(device, it, messages, "assemble", |d, p| {
+                                    assembler::assemble_path(&d.instruction_set, p)
+                                });
+                                history.clear();
+                                history.record(device);
+                                break;
+                            }
+                            Some("compile") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                build_command(device, it, messages, "compile", |_, p| {
+                                    compiler::compile_path(p)
+                                });
+                                history.clear();
+                                history.record(device);
+                                break;
+                            }
                             Some("clear") | Some("cl") => {
                                 let it = it.flat_map(|s| s.split(" "));
-                                clear_command(it, messages, hooks);
+                                clear_command(it, messages, breakpoints);
+                                triggered.clear();
                                 break;
                             }
                             Some("inspect") => {
                                 let it = it.flat_map(|s| s.split(" "));
-                                inspect_command(it, messages, hooks);
+                                inspect_command(it, messages, breakpoints);
+                                break;
+                            }
+                            Some("ignore") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                ignore_command(it, messages, breakpoints);
+                                break;
+                            }
+                            Some(command @ "enable") | Some(command @ "disable") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                let enabled = command == "enable";
+
+                                if let Some(b) = breakpoint(it, messages, breakpoints) {
+                                    b.enabled = enabled;
+                                }
+
+                                break;
+                            }
+                            Some("watch") => {
+                                let expr = match it.next() {
+                                    Some(expr) => expr,
+                                    None => {
+                                        messages.push(Message::error("missing watch expression!"));
+                                        break;
+                                    }
+                                };
+
+                                if let Ok(expr) =
+                                    parse_input(device, expr, messages, parser::parse_expr)
+                                {
+                                    let mut watch = Watch { expr, last: None };
+                                    watch.snapshot(device);
+                                    watches.push(watch);
+                                }
+
+                                break;
+                            }
+                            Some("p") | Some("print") | Some("eval") => {
+                                let expr = match it.next() {
+                                    Some(expr) => expr,
+                                    None => {
+                                        messages.push(Message::error("missing expression!"));
+                                        break;
+                                    }
+                                };
+
+                                if let Ok(parsed) =
+                                    parse_input(device, expr, messages, parser::parse_print)
+                                {
+                                    print_command(device, parsed, messages);
+                                }
+
+                                break;
+                            }
+                            Some("unwatch") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                unwatch_command(it, messages, watches);
+                                break;
+                            }
+                            Some("history") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                history_command(device, history, it, messages);
+                                break;
+                            }
+                            Some("log") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                log_command(log, it, messages);
+                                break;
+                            }
+                            Some("format") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                format_command(device, it, messages);
                                 break;
                             }
                             Some("set") => {
@@ -563,6 +1266,11 @@ impl Visuals for Terminal {
                                 set_command(device, it, messages)?;
                                 break;
                             }
+                            Some("mode") => {
+                                let it = it.flat_map(|s| s.split(" "));
+                                mode_command(device, it, messages);
+                                break;
+                            }
                             Some(command) => {
                                 messages
                                     .push(Message::error(format!("no such command: {}", command)));
@@ -589,13 +1297,7 @@ impl Visuals for Terminal {
                         }
                     }
                     e => {
-                        generic_handle(
-                            e,
-                            human_decoding,
-                            device.instructions.len(),
-                            scroll,
-                            messages,
-                        );
+                        generic_handle(e, human_decoding, device, scroll, log, messages);
                         break;
                     }
                 }
@@ -605,10 +1307,14 @@ impl Visuals for Terminal {
         fn generic_handle(
             e: Event,
             human_decoding: &mut bool,
-            len: usize,
+            device: &mut Device,
             scroll: &mut usize,
+            log: &mut Log,
             messages: &mut Vec<Message>,
         ) {
+            let len = device.instructions.len();
+            let page = LOG_HEIGHT as usize - 2;
+
             match e {
                 Event::Input(Key::Up) => {
                     *scroll = scroll.saturating_sub(1);
@@ -616,24 +1322,45 @@ impl Visuals for Terminal {
                 Event::Input(Key::Down) => {
                     *scroll = usize::min(scroll.saturating_add(1), len.saturating_sub(1));
                 }
+                Event::Input(Key::PageUp) => {
+                    log.scroll_up(page);
+                }
+                Event::Input(Key::PageDown) => {
+                    log.scroll_down(page);
+                }
                 Event::Input(Key::F(1)) => {
                     *human_decoding = !*human_decoding;
                 }
+                Event::Input(Key::F(2)) => {
+                    let format = device.registers.default_format().next();
+                    device.registers.set_default_format(format);
+                }
                 e => {
                     messages.push(Message::error(format!("unhandled event: {:?}", e)));
                 }
             }
         }
 
-        fn parse_hook<'a>(
+        /// Parse a condition or expression, pointing out where parsing failed.
+        fn parse_input<T>(
             device: &Device,
             condition: &str,
             messages: &mut Vec<Message>,
-        ) -> Result<Hook, ()> {
-            match parser::parse(condition, device) {
-                Ok(hook) => Ok(hook),
+            parse: impl FnOnce(&str, &Device) -> Result<T, Error>,
+        ) -> Result<T, ()> {
+            match parse(condition, device) {
+                Ok(value) => Ok(value),
                 Err(e) => {
-                    messages.push(Message::error(format!("bad condition: {}", e)));
+                    if let Some(e) = e.downcast_ref::<parser::ParseError>() {
+                        messages.push(Message::info(format!("  {}", condition)));
+                        messages.push(Message::error(format!(
+                            "  {:>width$}",
+                            "^",
+                            width = e.column
+                        )));
+                    }
+
+                    messages.push(Message::error(format!("bad input: {}", e)));
                     Err(())
                 }
             }
@@ -660,10 +1387,181 @@ impl Visuals for Terminal {
             }
         }
 
+        fn opcodes_command<'a>(
+            device: &mut Device,
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+        ) {
+            let path = match it.next() {
+                Some(path) if !path.is_empty() => path,
+                _ => {
+                    for (id, op_code) in device.op_code_map.iter() {
+                        messages.push(Message::info(format!("{:<2}: {}", id, op_code)));
+                    }
+
+                    for (id, op_code) in device.program_op_codes().iter() {
+                        messages.push(Message::info(format!(
+                            "{:<2}: {} (from the program)",
+                            id, op_code
+                        )));
+                    }
+
+                    if device.op_code_map.is_empty() && device.program_op_codes().is_empty() {
+                        messages.push(Message::info("no op codes are mapped"));
+                    }
+
+                    return;
+                }
+            };
+
+            match OpCodeMap::load_path(&device.instruction_set, path) {
+                Ok(map) => {
+                    messages.push(Message::info(format!(
+                        "mapped {} op codes from `{}`",
+                        map.len(),
+                        path
+                    )));
+                    device.op_code_map = map;
+                }
+                Err(e) => {
+                    messages.push(Message::error(format!(
+                        "problem when loading `{}`: {}",
+                        path, e
+                    )));
+                }
+            }
+        }
+
+        /// Infer the mapping of numeric op codes from samples, and either use
+        /// it or write it to a file.
+        fn infer_command<'a>(
+            device: &mut Device,
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+        ) {
+            let path = match it.next() {
+                Some(path) if !path.is_empty() => path,
+                _ => {
+                    messages.push(Message::error("expected: infer <path> [output]"));
+                    return;
+                }
+            };
+
+            let samples = match analysis::load_samples_path(path) {
+                Ok(samples) => samples,
+                Err(e) => {
+                    messages.push(Message::error(format!(
+                        "problem when loading `{}`: {}",
+                        path, e
+                    )));
+                    return;
+                }
+            };
+
+            let analysis = analysis::analyze(
+                &device.instruction_set,
+                device.registers.arithmetic(),
+                &samples,
+            );
+
+            messages.push(Message::info(format!(
+                "{} of {} samples match three or more op codes",
+                analysis.ambiguous.len(),
+                analysis.samples
+            )));
+
+            if !analysis.ambiguous.is_empty() {
+                let lines = analysis
+                    .ambiguous
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect::<Vec<_>>();
+
+                messages.push(Message::info(format!(
+                    "samples on lines: {}",
+                    lines.join(", ")
+                )));
+            }
+
+            let map = match analysis.solve() {
+                Ok(map) => map,
+                Err(e) => {
+                    messages.push(Message::error(e.to_string()));
+                    return;
+                }
+            };
+
+            match it.next() {
+                Some(output) => match std::fs::write(output, map.display().to_string()) {
+                    Ok(()) => messages.push(Message::info(format!("wrote `{}`", output))),
+                    Err(e) => messages.push(Message::error(format!(
+                        "problem when writing `{}`: {}",
+                        output, e
+                    ))),
+                },
+                None => {
+                    messages.push(Message::info(format!("mapped {} op codes", map.len())));
+                    device.op_code_map = map;
+                }
+            }
+        }
+
+        /// Build a program using the given function, and either load it or
+        /// write it to a file.
+        fn build_command<'a>(
+            device: &mut Device,
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+            command: &str,
+            build: fn(&Device, &str) -> Result<Program, Error>,
+        ) {
+            let path = match it.next() {
+                Some(path) => path,
+                None => {
+                    messages.push(Message::error(format!(
+                        "expected: {} <path> [output]",
+                        command
+                    )));
+                    return;
+                }
+            };
+
+            let program = match build(device, path) {
+                Ok(program) => program,
+                Err(e) => {
+                    messages.push(Message::error(format!(
+                        "problem when building `{}`: {}",
+                        path, e
+                    )));
+                    return;
+                }
+            };
+
+            let result = match it.next() {
+                Some(output) => {
+                    let result = std::fs::write(output, program.display().to_string());
+
+                    if result.is_ok() {
+                        messages.push(Message::info(format!("wrote `{}`", output)));
+                    }
+
+                    result.map_err(Error::from)
+                }
+                None => device.load_program(program),
+            };
+
+            if let Err(e) = result {
+                messages.push(Message::error(format!(
+                    "problem when building `{}`: {}",
+                    path, e
+                )));
+            }
+        }
+
         fn clear_command<'a>(
             mut it: impl Iterator<Item = &'a str>,
             messages: &mut Vec<Message>,
-            hooks: &mut Vec<Hook>,
+            breakpoints: &mut Vec<Breakpoint>,
         ) {
             let index = match it.next() {
                 Some(index) => match str::parse(index) {
@@ -674,27 +1572,27 @@ impl Visuals for Terminal {
                     }
                 },
                 None => {
-                    if hooks.is_empty() {
+                    if breakpoints.is_empty() {
                         messages.push(Message::error("no breakpoints to clear"));
                         return;
                     }
 
-                    hooks.len() - 1
+                    breakpoints.len() - 1
                 }
             };
 
-            if index >= hooks.len() {
+            if index >= breakpoints.len() {
                 messages.push(Message::error(format!("bad hook index `{}`", index)));
                 return;
             }
 
-            hooks.remove(index);
+            breakpoints.remove(index);
         }
 
         fn inspect_command<'a>(
             mut it: impl Iterator<Item = &'a str>,
             messages: &mut Vec<Message>,
-            hooks: &mut Vec<Hook>,
+            breakpoints: &mut Vec<Breakpoint>,
         ) {
             let index = match it.next() {
                 Some(index) => match str::parse(index) {
@@ -705,18 +1603,18 @@ impl Visuals for Terminal {
                     }
                 },
                 None => {
-                    if hooks.is_empty() {
+                    if breakpoints.is_empty() {
                         messages.push(Message::error("no breakpoints to clear"));
                         return;
                     }
 
-                    hooks.len() - 1
+                    breakpoints.len() - 1
                 }
             };
 
-            match hooks.get(index) {
-                Some(hook) => {
-                    messages.push(Message::info(hook.inspect().to_string()));
+            match breakpoints.get(index) {
+                Some(breakpoint) => {
+                    messages.push(Message::info(breakpoint.inspect().to_string()));
                 }
                 None => {
                     messages.push(Message::error(format!("no hook with index `{}`", index)));
@@ -724,6 +1622,262 @@ impl Visuals for Terminal {
             }
         }
 
+        fn print_command(
+            device: &Device,
+            (expr, format): (Expr, Option<Format>),
+            messages: &mut Vec<Message>,
+        ) {
+            let value = match expr.eval(&device.registers) {
+                Ok(value) => value,
+                Err(e) => {
+                    messages.push(Message::error(format!("failed to evaluate: {}", e)));
+                    return;
+                }
+            };
+
+            let name = expr.display(&device.registers);
+
+            let m = match format {
+                Some(format) => format!("{} = {}", name, format.display(&value)),
+                None => format!(
+                    "{} = {} ({}, {})",
+                    name,
+                    Format::Decimal.display(&value),
+                    Format::Hex.display(&value),
+                    Format::Binary.display(&value)
+                ),
+            };
+
+            messages.push(Message::info(m));
+        }
+
+        fn log_command<'a>(
+            log: &mut Log,
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+        ) {
+            match (it.next(), it.next()) {
+                (Some("file"), Some(path)) if !path.is_empty() => match log.open(path) {
+                    Ok(()) => {
+                        messages.push(Message::info(format!("writing log to `{}`", path)));
+                    }
+                    Err(e) => {
+                        messages.push(Message::error(format!("failed to open `{}`: {}", path, e)));
+                    }
+                },
+                (Some("close"), None) => match log.file.take() {
+                    Some((path, mut file)) => match file.flush() {
+                        Ok(()) => {
+                            messages
+                                .push(Message::info(format!("stopped writing log to `{}`", path)));
+                        }
+                        Err(e) => {
+                            messages.push(Message::error(format!(
+                                "failed to write log to `{}`: {}",
+                                path, e
+                            )));
+                        }
+                    },
+                    None => {
+                        messages.push(Message::error("log is not written to a file"));
+                    }
+                },
+                (Some("clear"), None) => {
+                    log.entries.clear();
+                    log.scroll = 0;
+                }
+                _ => {
+                    messages.push(Message::error("expected: log file <path> | close | clear"));
+                }
+            }
+        }
+
+        fn history_command<'a>(
+            device: &Device,
+            history: &History,
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+        ) {
+            let reg = match register(device, it.next(), messages) {
+                Some(reg) => reg,
+                None => {
+                    messages.push(Message::error("expected: history <register> [count]"));
+                    return;
+                }
+            };
+
+            let count = match it.next().map(str::parse::<usize>) {
+                Some(Ok(count)) => count,
+                Some(Err(e)) => {
+                    messages.push(Message::error(format!("bad count: {}", e)));
+                    return;
+                }
+                None => 10,
+            };
+
+            let format = device.registers.format(reg);
+            let samples = history.get(reg).rev().take(count).collect::<Vec<_>>();
+
+            messages.push(Message::bold(format!(
+                "last {} value(s) of {}:",
+                samples.len(),
+                device.registers.name(reg)
+            )));
+
+            for sample in samples.into_iter().rev() {
+                messages.push(Message::info(format!(
+                    "  step {:>12}: {}",
+                    thousands(sample.step),
+                    format.display(&sample.value)
+                )));
+            }
+        }
+
+        fn format_command<'a>(
+            device: &mut Device,
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+        ) {
+            let name = match it.next() {
+                Some(name) => name,
+                None => {
+                    messages.push(Message::info(format!(
+                        "default format: {}",
+                        device.registers.default_format()
+                    )));
+
+                    for reg in 0..device.registers.len() {
+                        messages.push(Message::info(format!(
+                            "  {}: {}",
+                            device.registers.name(reg),
+                            device.registers.format(reg)
+                        )));
+                    }
+
+                    return;
+                }
+            };
+
+            let format = match it.next() {
+                Some(format) => match Format::by_name(format) {
+                    Some(format) => format,
+                    None => {
+                        messages.push(Message::error(format!(
+                            "no such format `{}`, expected one of: {}",
+                            format,
+                            Format::names().join(", ")
+                        )));
+                        return;
+                    }
+                },
+                None => {
+                    messages.push(Message::error("expected: format <reg|all> <format>"));
+                    return;
+                }
+            };
+
+            if name == "all" {
+                device.registers.set_default_format(format);
+                return;
+            }
+
+            match device.registers.lookup(name) {
+                Some(reg) => device.registers.set_format(reg, format),
+                None => messages.push(Message::error(format!("no such register `{}`", name))),
+            }
+        }
+
+        fn unwatch_command<'a>(
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+            watches: &mut Vec<Watch>,
+        ) {
+            let index = match it.next() {
+                Some(index) => match str::parse(index) {
+                    Ok(index) => index,
+                    Err(e) => {
+                        messages.push(Message::error(format!("bad index `{}`: {}", index, e)));
+                        return;
+                    }
+                },
+                None => {
+                    if watches.is_empty() {
+                        messages.push(Message::error("no watches to remove"));
+                        return;
+                    }
+
+                    watches.len() - 1
+                }
+            };
+
+            if index >= watches.len() {
+                messages.push(Message::error(format!("no watch with index `{}`", index)));
+                return;
+            }
+
+            watches.remove(index);
+        }
+
+        fn ignore_command<'a>(
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+            breakpoints: &mut Vec<Breakpoint>,
+        ) {
+            let index = it.next();
+
+            let count = match it.next().map(|n| (n, str::parse::<u64>(n))) {
+                Some((_, Ok(count))) => count,
+                Some((n, Err(e))) => {
+                    messages.push(Message::error(format!("bad count `{}`: {}", n, e)));
+                    return;
+                }
+                None => {
+                    messages.push(Message::error("expected: ignore <index> <count>"));
+                    return;
+                }
+            };
+
+            if let Some(b) = breakpoint(index.into_iter(), messages, breakpoints) {
+                b.ignore = count;
+            }
+        }
+
+        /// Look up a breakpoint by index, defaulting to the last one.
+        fn breakpoint<'a, 'b>(
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+            breakpoints: &'b mut Vec<Breakpoint>,
+        ) -> Option<&'b mut Breakpoint> {
+            let index = match it.next() {
+                Some(index) => match str::parse(index) {
+                    Ok(index) => index,
+                    Err(e) => {
+                        messages.push(Message::error(format!("bad index `{}`: {}", index, e)));
+                        return None;
+                    }
+                },
+                None => {
+                    if breakpoints.is_empty() {
+                        messages.push(Message::error("no breakpoints"));
+                        return None;
+                    }
+
+                    breakpoints.len() - 1
+                }
+            };
+
+            match breakpoints.get_mut(index) {
+                Some(breakpoint) => Some(breakpoint),
+                None => {
+                    messages.push(Message::error(format!(
+                        "no breakpoint with index `{}`",
+                        index
+                    )));
+                    None
+                }
+            }
+        }
+
         fn set_command<'a>(
             device: &mut Device,
             mut it: impl Iterator<Item = &'a str>,
@@ -738,7 +1892,7 @@ impl Visuals for Terminal {
             };
 
             let value = match it.next() {
-                Some(value) => match str::parse(value) {
+                Some(value) => match str::parse::<Reg>(value) {
                     Ok(value) => value,
                     Err(e) => {
                         messages.push(Message::error(format!("bad value `{}`: {}", value, e)));
@@ -751,28 +1905,68 @@ impl Visuals for Terminal {
                 }
             };
 
-            *device.registers.reg_mut(reg)? = value;
+            if let Err(e) = device.registers.set(reg, value) {
+                messages.push(Message::error(e.to_string()));
+            }
+
             Ok(())
         }
 
+        fn mode_command<'a>(
+            device: &mut Device,
+            mut it: impl Iterator<Item = &'a str>,
+            messages: &mut Vec<Message>,
+        ) {
+            let name = match it.next() {
+                Some(name) if !name.is_empty() => name,
+                _ => {
+                    messages.push(Message::info(format!(
+                        "arithmetic mode: {}",
+                        device.registers.arithmetic()
+                    )));
+                    return;
+                }
+            };
+
+            let mode = match Arithmetic::by_name(name) {
+                Some(mode) => mode,
+                None => {
+                    messages.push(Message::error(format!(
+                        "no such mode `{}`, expected one of: {}",
+                        name,
+                        Arithmetic::names().join(", ")
+                    )));
+                    return;
+                }
+            };
+
+            if let Err(e) = device.registers.set_arithmetic(mode) {
+                messages.push(Message::error(format!(
+                    "can't switch to `{}` mode: {}",
+                    mode, e
+                )));
+                return;
+            }
+
+            messages.push(Message::info(format!("arithmetic mode: {}", mode)));
+        }
+
         fn register(
             device: &Device,
             reg: Option<&str>,
             messages: &mut Vec<Message>,
         ) -> Option<usize> {
             let reg = match reg {
-                Some("a") => 0,
-                Some("b") => 1,
-                Some("c") => 2,
-                Some("d") => 3,
-                Some("e") => 4,
-                Some("f") => 5,
-                Some("ip") => device.registers.ip,
-                Some(o) => {
-                    messages.push(Message::error(format!("bad register: {}", o)));
+                Some(reg) => reg,
+                None => return None,
+            };
+
+            let reg = match device.registers.lookup(reg) {
+                Some(reg) => reg,
+                None => {
+                    messages.push(Message::error(format!("bad register: {}", reg)));
                     return None;
                 }
-                None => return None,
             };
 
             Some(reg)